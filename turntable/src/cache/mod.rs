@@ -0,0 +1,5 @@
+mod packument;
+mod tarball;
+
+pub use packument::*;
+pub use tarball::*;
@@ -0,0 +1,85 @@
+use std::{
+  io::ErrorKind,
+  path::{Path, PathBuf},
+};
+
+use tokio::fs;
+
+use crate::{
+  config::CacheConfig,
+  registry::{encode_package_name, Packument},
+  utils::fs::write_atomic,
+};
+
+/// The last packument fetched for each package, kept next to the tarball
+/// cache so packages stay resolvable offline.
+pub struct PackumentCache {
+  dir: Option<PathBuf>,
+}
+
+impl PackumentCache {
+  pub fn disabled() -> Self {
+    Self { dir: None }
+  }
+
+  pub fn open(config: &CacheConfig) -> anyhow::Result<Self> {
+    let Some(dir) = config.dir.as_ref() else {
+      return Ok(Self::disabled());
+    };
+    let dir = dir.join("packuments");
+    std::fs::create_dir_all(&dir)?;
+    Ok(Self { dir: Some(dir) })
+  }
+
+  pub async fn get(&self, package_name: &str) -> Option<Packument> {
+    let path = packument_path(self.dir.as_ref()?, package_name);
+    match fs::read(&path).await {
+      Ok(content) => serde_json::from_slice(&content)
+        .map_err(|e| tracing::warn!("Ignoring unreadable cached packument {package_name}: {e}"))
+        .ok(),
+      Err(e) if e.kind() == ErrorKind::NotFound => None,
+      Err(e) => {
+        tracing::warn!("Error reading cached packument {package_name}: {e}");
+        None
+      }
+    }
+  }
+
+  pub async fn put(&self, package_name: &str, packument: &Packument) -> anyhow::Result<()> {
+    let Some(dir) = self.dir.as_ref() else {
+      return Ok(());
+    };
+    let content = serde_json::to_vec(packument)?;
+    write_atomic(&packument_path(dir, package_name), &content).await
+  }
+}
+
+/// `@scope/name` is stored at `@scope%2Fname.json`.
+fn packument_path(dir: &Path, package_name: &str) -> PathBuf {
+  dir.join(format!("{}.json", encode_package_name(package_name)))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn test_get_and_put() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let cache = PackumentCache::open(&CacheConfig {
+      dir: Some(dir.path().to_path_buf()),
+      ..Default::default()
+    })?;
+    let packument = Packument {
+      dist_tags: [("latest".to_owned(), "1.0.0".to_owned())].into(),
+      ..Default::default()
+    };
+
+    assert!(cache.get("@scope/pkg").await.is_none());
+    cache.put("@scope/pkg", &packument).await?;
+    let cached = cache.get("@scope/pkg").await.unwrap();
+    assert_eq!(cached.dist_tags, packument.dist_tags);
+    assert!(dir.path().join("packuments/@scope%2Fpkg.json").is_file());
+    Ok(())
+  }
+}
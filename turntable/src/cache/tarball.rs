@@ -0,0 +1,317 @@
+use std::{
+  collections::HashMap,
+  io::ErrorKind,
+  path::{Path, PathBuf},
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use tokio::{fs, sync::Mutex};
+
+use crate::{
+  config::CacheConfig,
+  utils::{encrypt::verify_integrity, fs::write_atomic},
+};
+
+const INDEX_FILE: &str = "index.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Record {
+  integrity: String,
+  size: u64,
+  last_access: u64,
+}
+
+#[derive(Debug, Default)]
+struct Index {
+  records: HashMap<String, Record>,
+  size: u64,
+}
+
+impl Index {
+  fn new(records: HashMap<String, Record>) -> Self {
+    let mut index = Index::default();
+    for (key, record) in records {
+      index.insert(key, record);
+    }
+    index
+  }
+
+  #[inline]
+  fn is_shared(&self, integrity: &str) -> bool {
+    self
+      .records
+      .values()
+      .any(|record| record.integrity == integrity)
+  }
+
+  fn insert(&mut self, key: String, record: Record) -> Option<Record> {
+    let removed = self.remove(&key);
+    if !self.is_shared(&record.integrity) {
+      self.size += record.size;
+    }
+    self.records.insert(key, record);
+    removed
+  }
+
+  /// Removes `key` and returns its record when no other key points at the same blob.
+  fn remove(&mut self, key: &str) -> Option<Record> {
+    let record = self.records.remove(key)?;
+    if self.is_shared(&record.integrity) {
+      return None;
+    }
+    self.size -= record.size;
+    Some(record)
+  }
+
+  fn least_recently_used(&self, except: &str) -> Option<String> {
+    self
+      .records
+      .iter()
+      .filter(|(key, _)| *key != except)
+      .min_by_key(|(_, record)| record.last_access)
+      .map(|(key, _)| key.to_owned())
+  }
+}
+
+/// Content-addressed tarball store on disk. Entries are keyed by `name@version`,
+/// checked against the registry integrity on every read and evicted least
+/// recently used first once the store grows past `max_size`.
+pub struct TarballCache {
+  dir: Option<PathBuf>,
+  max_size: u64,
+  index: Mutex<Index>,
+}
+
+impl TarballCache {
+  pub fn disabled() -> Self {
+    Self {
+      dir: None,
+      max_size: 0,
+      index: Default::default(),
+    }
+  }
+
+  pub fn open(config: &CacheConfig) -> anyhow::Result<Self> {
+    let Some(dir) = config.dir.clone() else {
+      return Ok(Self::disabled());
+    };
+    std::fs::create_dir_all(dir.join("content"))?;
+
+    let mut records: HashMap<String, Record> = match std::fs::read(dir.join(INDEX_FILE)) {
+      Ok(content) => serde_json::from_slice(&content).unwrap_or_else(|e| {
+        tracing::warn!("Ignoring unreadable tarball cache index: {e}");
+        Default::default()
+      }),
+      Err(e) if e.kind() == ErrorKind::NotFound => Default::default(),
+      Err(e) => return Err(e.into()),
+    };
+    records.retain(|_, record| content_path(&dir, &record.integrity).is_file());
+
+    let index = Index::new(records);
+    tracing::debug!(
+      "Opened tarball cache at {} ({} entries, {} bytes)",
+      dir.display(),
+      index.records.len(),
+      index.size
+    );
+
+    Ok(Self {
+      dir: Some(dir),
+      max_size: config.max_size,
+      index: Mutex::new(index),
+    })
+  }
+
+  /// Reads and verifies outside the index lock, so cache hits do not wait on
+  /// each other's disk reads.
+  pub async fn get(&self, key: &str, integrity: &str) -> Option<Bytes> {
+    let dir = self.dir.as_ref()?;
+    {
+      let mut index = self.index.lock().await;
+      let record = index.records.get_mut(key)?;
+      if record.integrity != integrity {
+        return None;
+      }
+      record.last_access = now();
+    }
+
+    match fs::read(content_path(dir, integrity)).await {
+      Ok(content) if verify_integrity(&content, integrity) => Some(content.into()),
+      result => {
+        tracing::warn!("Dropping corrupted tarball cache entry {key}: {result:?}");
+        let mut index = self.index.lock().await;
+        // It may have been replaced or evicted while unlocked.
+        if matches!(index.records.get(key), Some(record) if record.integrity == integrity) {
+          if let Some(record) = index.remove(key) {
+            remove_content(dir, &record.integrity).await;
+          }
+        }
+        None
+      }
+    }
+  }
+
+  /// Whether `key` is stored with `integrity`, without reading its content.
+  pub async fn contains(&self, key: &str, integrity: &str) -> bool {
+    let index = self.index.lock().await;
+    self.dir.is_some()
+      && matches!(index.records.get(key), Some(record) if record.integrity == integrity)
+  }
+
+  /// Stores `content` under `key`. Tarballs larger than `max_size` are
+  /// rejected rather than evicting everything else.
+  pub async fn put(&self, key: &str, integrity: &str, content: &Bytes) -> anyhow::Result<()> {
+    let Some(dir) = self.dir.as_ref() else {
+      return Ok(());
+    };
+    let size = content.len() as u64;
+    if size > self.max_size {
+      anyhow::bail!(
+        "tarball for {key} is {size} bytes, more than the whole cache (max_size {})",
+        self.max_size
+      );
+    }
+    if !verify_integrity(content, integrity) {
+      anyhow::bail!("tarball for {key} does not match {integrity}");
+    }
+
+    let mut index = self.index.lock().await;
+
+    let path = content_path(dir, integrity);
+    if !path.is_file() {
+      write_atomic(&path, content).await?;
+    }
+
+    let record = Record {
+      integrity: integrity.to_owned(),
+      size,
+      last_access: now(),
+    };
+    match index.insert(key.to_owned(), record) {
+      Some(replaced) if replaced.integrity != integrity => {
+        remove_content(dir, &replaced.integrity).await;
+      }
+      _ => {}
+    }
+
+    while index.size > self.max_size {
+      let Some(lru) = index.least_recently_used(key) else {
+        break;
+      };
+      tracing::debug!("Evicting {lru} from the tarball cache");
+      if let Some(record) = index.remove(&lru) {
+        remove_content(dir, &record.integrity).await;
+      }
+    }
+
+    let records = serde_json::to_vec(&index.records)?;
+    write_atomic(&dir.join(INDEX_FILE), &records).await
+  }
+}
+
+#[inline]
+fn now() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_millis() as u64)
+    .unwrap_or_default()
+}
+
+/// `sha512-ab/c+d==` is stored at `content/sha512/ab_c-d==`.
+fn content_path(dir: &Path, integrity: &str) -> PathBuf {
+  let hash = integrity.split_whitespace().next().unwrap_or_default();
+  let (algorithm, digest) = hash.split_once('-').unwrap_or(("unknown", hash));
+  dir
+    .join("content")
+    .join(algorithm)
+    .join(digest.replace('/', "_").replace('+', "-"))
+}
+
+async fn remove_content(dir: &Path, integrity: &str) {
+  if let Err(e) = fs::remove_file(content_path(dir, integrity)).await {
+    tracing::warn!("Error removing cached tarball {integrity}: {e}");
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::utils::encrypt::get_intergrity;
+
+  fn open(dir: &Path, max_size: u64) -> TarballCache {
+    TarballCache::open(&CacheConfig {
+      dir: Some(dir.to_path_buf()),
+      max_size,
+      ..Default::default()
+    })
+    .unwrap()
+  }
+
+  #[tokio::test]
+  async fn test_get_and_put() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let cache = open(dir.path(), 1024);
+    let content = Bytes::from_static(b"tarball");
+    let integrity = get_intergrity(&content)?;
+
+    assert!(cache.get("a@1.0.0", &integrity).await.is_none());
+    cache.put("a@1.0.0", &integrity, &content).await?;
+    assert_eq!(
+      cache.get("a@1.0.0", &integrity).await,
+      Some(content.clone())
+    );
+    assert!(cache.get("a@1.0.0", "sha384-other").await.is_none());
+    assert!(cache
+      .put("b@1.0.0", "sha384-other", &content)
+      .await
+      .is_err());
+
+    let cache = open(dir.path(), 1024);
+    assert_eq!(cache.get("a@1.0.0", &integrity).await, Some(content));
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_reject_larger_than_max_size() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let cache = open(dir.path(), 4);
+    let content = Bytes::from_static(b"tarball");
+    let integrity = get_intergrity(&content)?;
+
+    assert!(cache.put("a@1.0.0", &integrity, &content).await.is_err());
+    assert!(!cache.contains("a@1.0.0", &integrity).await);
+    assert!(!content_path(dir.path(), &integrity).exists());
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_evict_least_recently_used() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let cache = open(dir.path(), 10);
+    let (a, b, c) = (
+      Bytes::from_static(b"aaaa"),
+      Bytes::from_static(b"bbbb"),
+      Bytes::from_static(b"cccc"),
+    );
+    let (ia, ib, ic) = (
+      get_intergrity(&a)?,
+      get_intergrity(&b)?,
+      get_intergrity(&c)?,
+    );
+
+    cache.put("a@1.0.0", &ia, &a).await?;
+    cache.put("b@1.0.0", &ib, &b).await?;
+    tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+    cache.get("a@1.0.0", &ia).await;
+    cache.put("c@1.0.0", &ic, &c).await?;
+
+    assert!(cache.get("a@1.0.0", &ia).await.is_some());
+    assert!(cache.get("b@1.0.0", &ib).await.is_none());
+    assert!(cache.get("c@1.0.0", &ic).await.is_some());
+    assert!(!content_path(dir.path(), &ib).exists());
+    Ok(())
+  }
+}
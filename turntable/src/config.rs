@@ -0,0 +1,480 @@
+use std::{
+  collections::HashMap,
+  fmt, fs,
+  path::{Path, PathBuf},
+  sync::Arc,
+};
+
+use anyhow::Context;
+use clap::{Args, Parser, Subcommand};
+use poem::{FromRequest, Request, RequestBody};
+use reqwest::Url;
+use serde::Deserialize;
+
+use crate::policy::Policy;
+
+/// Command line flags. Each flag falls back to its environment variable, so
+/// the merge order is: defaults < config file < environment < flags.
+#[derive(Debug, Default, Parser)]
+#[command(version, about)]
+pub struct Cli {
+  #[command(subcommand)]
+  pub command: Option<Command>,
+  /// Path of a TOML config file
+  #[arg(short, long, global = true, env = "TURNTABLE_CONFIG")]
+  pub config: Option<PathBuf>,
+  /// Port to listen on
+  #[arg(short, long, global = true, env = "PORT")]
+  pub port: Option<u16>,
+  /// Public origin used when rewriting module specifiers
+  #[arg(long, global = true, env = "ORIGIN")]
+  pub origin: Option<String>,
+  /// Upstream npm registry, or a `file://` directory of packages
+  #[arg(long, global = true, env = "NPM_REGISTRY_URL")]
+  pub npm_registry_url: Option<String>,
+  /// Bearer token for the upstream npm registry
+  #[arg(long, global = true, env = "NPM_REGISTRY_TOKEN", hide_env_values = true)]
+  pub npm_registry_token: Option<String>,
+  /// Directory for the persistent tarball cache
+  #[arg(long, global = true, env = "TURNTABLE_CACHE_DIR")]
+  pub cache_dir: Option<PathBuf>,
+  /// Size limit of the tarball cache in bytes
+  #[arg(long, global = true, env = "TURNTABLE_CACHE_MAX_SIZE")]
+  pub cache_max_size: Option<u64>,
+  /// Serve only packages already in the cache, never contacting a registry
+  #[arg(long, global = true, env = "TURNTABLE_OFFLINE")]
+  pub offline: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+  /// Serve packages over HTTP (the default)
+  Serve,
+  /// Download the packages of lockfiles into the cache
+  Prefetch(PrefetchArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct PrefetchArgs {
+  /// `package-lock.json`, `pnpm-lock.yaml`, `yarn.lock` or a file with one
+  /// `name@range` per line
+  #[arg(required = true)]
+  pub files: Vec<PathBuf>,
+  /// How many packages to download at once
+  #[arg(short = 'j', long, default_value_t = 8)]
+  pub concurrency: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+  pub port: u16,
+  pub origin: String,
+  pub npm_registry_url: String,
+  pub npm_registry_token: Option<Secret>,
+  /// Registries for scoped packages, keyed by scope (`@ourco`).
+  pub scopes: HashMap<String, ScopeConfig>,
+  pub cache: CacheConfig,
+  /// Resolve packages from `cache.dir` only.
+  pub offline: bool,
+  pub publish: PublishConfig,
+  pub policy: PolicyConfig,
+  pub limits: LimitsConfig,
+  pub assets: AssetsConfig,
+  pub jsx: JsxConfig,
+}
+
+/// A registry serving every package of one scope, like an `.npmrc`
+/// `@scope:registry=` line. Use either `token` or `username` and `password`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScopeConfig {
+  pub url: String,
+  pub token: Option<Secret>,
+  pub username: Option<String>,
+  pub password: Option<Secret>,
+}
+
+/// A credential that is kept out of `Debug` output.
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+  pub fn expose(&self) -> &str {
+    &self.0
+  }
+}
+
+impl From<String> for Secret {
+  fn from(value: String) -> Self {
+    Self(value)
+  }
+}
+
+impl fmt::Debug for Secret {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("\"***\"")
+  }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+  /// Tarballs are only cached on disk when a directory is set.
+  pub dir: Option<PathBuf>,
+  pub max_size: u64,
+  /// How many parsed package indexes are kept in memory.
+  pub max_indexes: usize,
+  /// How many bytes of unpacked archives those indexes may hold together.
+  pub max_index_size: u64,
+  /// Seconds a version list is fresh. After that it is still served while a
+  /// background refresh runs.
+  pub versions_ttl: u64,
+  /// Seconds past `versions_ttl` a stale version list may be served when the
+  /// registry cannot be reached. It counts from when the list was fetched, not
+  /// from the first failed refresh.
+  pub versions_grace: u64,
+}
+
+/// Packages published with `npm publish --registry` are stored in `dir` and
+/// served before any registry. Publishing needs one of `tokens`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PublishConfig {
+  pub dir: Option<PathBuf>,
+  pub tokens: Vec<Secret>,
+}
+
+/// Packages, versions and licenses that may not be served. A package must
+/// match an `allow` rule, when there are any, and no `deny` rule.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicyConfig {
+  pub allow: Vec<PolicyRule>,
+  pub deny: Vec<PolicyRule>,
+  /// SPDX license ids that may be served. Any license is served when empty.
+  pub licenses: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PolicyRule {
+  /// A package name, where `*` matches anything (`@evil/*`).
+  pub package: String,
+  /// A semver range. The rule covers every version when unset.
+  pub versions: Option<String>,
+  /// Shown to clients when the rule blocks a package.
+  pub reason: Option<String>,
+}
+
+/// Files served at fixed paths. The assets built into the binary, like
+/// `/favicon.ico`, are replaced by files of the same name in `dir`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AssetsConfig {
+  pub dir: Option<PathBuf>,
+  pub cache_control: String,
+  /// More directories to serve, like `/.well-known`.
+  pub routes: Vec<AssetsRoute>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AssetsRoute {
+  /// The path prefix the files of `dir` are served under.
+  pub path: String,
+  pub dir: PathBuf,
+  /// Defaults to `public, max-age=600`.
+  pub cache_control: Option<String>,
+}
+
+/// How `?module` compiles JSX in `.jsx` and `.tsx` files. A
+/// `@jsxImportSource` or `@jsx` comment in a file wins over these.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JsxConfig {
+  pub runtime: JsxRuntime,
+  /// The package `automatic` imports `jsx-runtime` from.
+  pub import_source: String,
+  /// What `classic` calls for elements and fragments.
+  pub pragma: String,
+  pub pragma_frag: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JsxRuntime {
+  #[default]
+  Automatic,
+  Classic,
+}
+
+impl Default for JsxConfig {
+  fn default() -> Self {
+    Self {
+      runtime: JsxRuntime::default(),
+      import_source: "react".into(),
+      pragma: "React.createElement".into(),
+      pragma_frag: "React.Fragment".into(),
+    }
+  }
+}
+
+/// Bounds on what one upstream fetch may take. Times are in seconds and sizes
+/// in bytes.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+  pub connect_timeout: u64,
+  /// The longest wait for the next chunk of a response.
+  pub read_timeout: u64,
+  /// The longest a whole request may take, body included.
+  pub timeout: u64,
+  pub max_tarball_size: u64,
+  /// Checked while decompressing, so a small tarball cannot unpack into
+  /// gigabytes.
+  pub max_unpacked_size: u64,
+}
+
+impl Default for LimitsConfig {
+  fn default() -> Self {
+    Self {
+      connect_timeout: 10,
+      read_timeout: 30,
+      timeout: 120,
+      max_tarball_size: 100 * 1024 * 1024,
+      max_unpacked_size: 512 * 1024 * 1024,
+    }
+  }
+}
+
+impl Default for AssetsConfig {
+  fn default() -> Self {
+    Self {
+      dir: None,
+      cache_control: "public, max-age=31536000".into(),
+      routes: Vec::new(),
+    }
+  }
+}
+
+impl Default for CacheConfig {
+  fn default() -> Self {
+    Self {
+      dir: None,
+      max_size: 1024 * 1024 * 1024,
+      max_indexes: 64,
+      max_index_size: 512 * 1024 * 1024,
+      versions_ttl: 300,
+      versions_grace: 24 * 60 * 60,
+    }
+  }
+}
+
+impl Default for Config {
+  fn default() -> Self {
+    Self {
+      port: 8080,
+      origin: "https://unpkg.com".into(),
+      npm_registry_url: "https://registry.npmmirror.com".into(),
+      npm_registry_token: None,
+      scopes: Default::default(),
+      cache: Default::default(),
+      offline: false,
+      publish: Default::default(),
+      policy: Default::default(),
+      limits: Default::default(),
+      assets: Default::default(),
+      jsx: Default::default(),
+    }
+  }
+}
+
+impl Config {
+  pub fn load(cli: &Cli) -> anyhow::Result<Self> {
+    let mut config = match cli.config {
+      Some(ref path) => Self::from_file(path)?,
+      None => Self::default(),
+    };
+    config.merge(cli);
+    config.validate()?;
+    Ok(config)
+  }
+
+  pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+    let content = fs::read_to_string(path)
+      .with_context(|| format!("read config file {}", path.display()))?;
+    toml::from_str(&content).with_context(|| format!("parse config file {}", path.display()))
+  }
+
+  fn merge(&mut self, cli: &Cli) {
+    if let Some(port) = cli.port {
+      self.port = port;
+    }
+    if let Some(ref origin) = cli.origin {
+      self.origin = origin.to_owned();
+    }
+    if let Some(ref url) = cli.npm_registry_url {
+      self.npm_registry_url = url.to_owned();
+    }
+    if let Some(ref token) = cli.npm_registry_token {
+      self.npm_registry_token = Some(token.to_owned().into());
+    }
+    if let Some(ref dir) = cli.cache_dir {
+      self.cache.dir = Some(dir.to_owned());
+    }
+    if let Some(max_size) = cli.cache_max_size {
+      self.cache.max_size = max_size;
+    }
+    if cli.offline {
+      self.offline = true;
+    }
+  }
+
+  fn validate(&mut self) -> anyhow::Result<()> {
+    self.origin = validate_url("origin", &self.origin, &["http", "https"])?;
+    self.npm_registry_url = validate_url(
+      "npm_registry_url",
+      &self.npm_registry_url,
+      &["http", "https", "file"],
+    )?;
+
+    if self.offline && self.cache.dir.is_none() {
+      anyhow::bail!("offline mode needs a cache directory (cache.dir)");
+    }
+    if self.publish.dir.is_some() && self.publish.tokens.is_empty() {
+      anyhow::bail!("publishing needs at least one token (publish.tokens)");
+    }
+    Policy::new(&self.policy).context("invalid policy")?;
+    for route in &self.assets.routes {
+      let path = route.path.trim_end_matches('/');
+      if !path.starts_with('/') || path.contains("//") || path == "/browse" {
+        anyhow::bail!(
+          "invalid assets route \"{}\" (expected a path like \"/.well-known\")",
+          route.path
+        );
+      }
+    }
+
+    for (scope, registry) in self.scopes.iter_mut() {
+      if !scope.starts_with('@') || scope.len() < 2 || scope.contains('/') {
+        anyhow::bail!("invalid scope \"{scope}\" (expected a name like \"@scope\")");
+      }
+      registry.url = validate_url(
+        &format!("scopes.\"{scope}\".url"),
+        &registry.url,
+        &["http", "https", "file"],
+      )?;
+      if registry.token.is_some() && (registry.username.is_some() || registry.password.is_some())
+      {
+        anyhow::bail!("scope \"{scope}\" cannot use both token and basic auth");
+      }
+      if registry.username.is_some() != registry.password.is_some() {
+        anyhow::bail!("scope \"{scope}\" needs both username and password");
+      }
+    }
+    Ok(())
+  }
+}
+
+#[inline]
+fn validate_url(key: &str, value: &str, schemes: &[&str]) -> anyhow::Result<String> {
+  let url = Url::parse(value).with_context(|| format!("invalid {key} \"{value}\""))?;
+  if !schemes.contains(&url.scheme()) {
+    anyhow::bail!(
+      "invalid {key} \"{value}\" (expected one of the schemes {})",
+      schemes.join(", ")
+    );
+  }
+  Ok(value.trim_end_matches('/').to_owned())
+}
+
+#[poem::async_trait]
+impl<'a> FromRequest<'a> for &'a Config {
+  async fn from_request(req: &'a Request, _: &mut RequestBody) -> poem::Result<Self> {
+    req
+      .extensions()
+      .get::<Arc<Config>>()
+      .map(|config| config.as_ref())
+      .ok_or(anyhow::anyhow!("get config from the request extensions"))
+      .map_err(Into::into)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_merge_order() {
+    let mut config: Config = toml::from_str(
+      r#"
+        port = 3000
+        origin = "https://cdn.example.com/"
+
+        [cache]
+        dir = "/var/cache/turntable"
+
+        [scopes."@ourco"]
+        url = "https://npm.ourco.internal/"
+        token = "s3cret"
+      "#,
+    )
+    .unwrap();
+    assert_eq!(config.npm_registry_url, Config::default().npm_registry_url);
+
+    let cli = Cli {
+      port: Some(4000),
+      ..Default::default()
+    };
+    config.merge(&cli);
+    config.validate().unwrap();
+
+    assert_eq!(config.port, 4000);
+    assert_eq!(config.origin, "https://cdn.example.com");
+    assert_eq!(config.cache.dir, Some(PathBuf::from("/var/cache/turntable")));
+    assert_eq!(config.cache.max_size, CacheConfig::default().max_size);
+
+    let scope = &config.scopes["@ourco"];
+    assert_eq!(scope.url, "https://npm.ourco.internal");
+    assert_eq!(scope.token.as_ref().map(Secret::expose), Some("s3cret"));
+    assert!(!format!("{config:?}").contains("s3cret"));
+  }
+
+  #[test]
+  fn test_validate_url() {
+    let mut config = Config {
+      npm_registry_url: "registry.npmjs.org".into(),
+      ..Default::default()
+    };
+    assert!(config.validate().is_err());
+
+    assert!(toml::from_str::<Config>("registry = \"x\"").is_err());
+
+    let mut config: Config = toml::from_str(
+      r#"
+        [scopes.ourco]
+        url = "https://npm.ourco.internal"
+      "#,
+    )
+    .unwrap();
+    assert!(config.validate().is_err());
+
+    let mut config = Config {
+      offline: true,
+      ..Default::default()
+    };
+    assert!(config.validate().is_err());
+
+    let mut config: Config = toml::from_str(
+      r#"
+        [[assets.routes]]
+        path = "/"
+        dir = "./public"
+      "#,
+    )
+    .unwrap();
+    assert!(config.validate().is_err());
+  }
+}
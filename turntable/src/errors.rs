@@ -1,12 +1,15 @@
+use std::sync::Arc;
+
 use poem::{error::ResponseError, http::header, IntoResponse, Response};
 use reqwest::StatusCode;
 use thiserror::Error;
 
-// Make our own error that wraps `anyhow::Error`.
-#[derive(Debug, Error)]
+// Make our own error that wraps `anyhow::Error`. It is shared behind an `Arc`
+// so that one failed upstream fetch can be handed to every request waiting on it.
+#[derive(Debug, Clone, Error)]
 pub enum AppError {
-  #[error(transparent)]
-  Any(#[from] anyhow::Error),
+  #[error("{0}")]
+  Any(Arc<anyhow::Error>),
   #[error("Invalid URL: {0}")]
   InvalidURL(String),
   #[error("Invalid package name \"{package_name}\" ({reason})")]
@@ -30,13 +33,56 @@ pub enum AppError {
     package_spec: String,
     filename: String,
   },
-  #[error("module mode is available only for JavaScript and HTML files")]
+  #[error("module mode is available only for JavaScript, TypeScript, HTML, JSON and CSS files")]
   InvalidContentTypeForModuleMode,
+  #[error("css mode is available only for CSS files")]
+  InvalidContentTypeForCssMode,
   #[error("Cannot generate module for {package_spec}{filename}")]
   UnableGenerateModule {
     package_spec: String,
     filename: String,
   },
+  #[error("Cannot fetch tarball for {package_spec} (upstream status: {status})")]
+  UnableFetchTarball { package_spec: String, status: u16 },
+  #[error("Tarball for {package_spec} does not match integrity {integrity}")]
+  TarballIntegrityMismatch {
+    package_spec: String,
+    integrity: String,
+  },
+  #[error("{0} is not available offline")]
+  NotAvailableOffline(String),
+  #[error("Unauthorized")]
+  Unauthorized,
+  #[error("Invalid publish request: {0}")]
+  InvalidPublish(String),
+  #[error("Cannot publish over the previously published version {0}")]
+  VersionAlreadyPublished(String),
+  #[error("{package_spec} is blocked ({reason})")]
+  PackageBlocked {
+    package_spec: String,
+    reason: String,
+  },
+  #[error("License \"{license}\" of {package_spec} is not allowed")]
+  LicenseNotAllowed {
+    package_spec: String,
+    license: String,
+  },
+  #[error("Tarball for {package_spec} is larger than {limit} bytes")]
+  TarballTooLarge { package_spec: String, limit: u64 },
+  #[error("Package {package_spec} unpacks to more than {limit} bytes")]
+  PackageTooLarge { package_spec: String, limit: u64 },
+  #[error("Timed out fetching {0} from the registry")]
+  UpstreamTimeout(String),
+}
+
+// Errors raised behind an `anyhow::Error`, e.g. by a `Registry`, keep their variant.
+impl From<anyhow::Error> for AppError {
+  fn from(error: anyhow::Error) -> Self {
+    match error.downcast::<AppError>() {
+      Ok(error) => error,
+      Err(error) => AppError::Any(Arc::new(error)),
+    }
+  }
 }
 
 // Tell axum how to convert `AppError` into a response.
@@ -46,9 +92,22 @@ impl ResponseError for AppError {
       AppError::InvalidURL(_) => StatusCode::FORBIDDEN,
       AppError::InvalidPackageName { .. } => StatusCode::FORBIDDEN,
       AppError::InvalidContentTypeForModuleMode => StatusCode::FORBIDDEN,
+      AppError::InvalidContentTypeForCssMode => StatusCode::FORBIDDEN,
       AppError::NotFoundPackage(_) => StatusCode::NOT_FOUND,
       AppError::NotFoundFileInPackage { .. } => StatusCode::NOT_FOUND,
       AppError::NotFoundIndexFileInPackage { .. } => StatusCode::NOT_FOUND,
+      AppError::UnableFetchTarball { status: 404, .. } => StatusCode::NOT_FOUND,
+      AppError::UnableFetchTarball { .. } => StatusCode::BAD_GATEWAY,
+      AppError::TarballIntegrityMismatch { .. } => StatusCode::BAD_GATEWAY,
+      AppError::NotAvailableOffline(_) => StatusCode::SERVICE_UNAVAILABLE,
+      AppError::Unauthorized => StatusCode::UNAUTHORIZED,
+      AppError::InvalidPublish(_) => StatusCode::BAD_REQUEST,
+      AppError::VersionAlreadyPublished(_) => StatusCode::CONFLICT,
+      AppError::PackageBlocked { .. } => StatusCode::FORBIDDEN,
+      AppError::LicenseNotAllowed { .. } => StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS,
+      AppError::TarballTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+      AppError::PackageTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+      AppError::UpstreamTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
       _ => StatusCode::INTERNAL_SERVER_ERROR,
     }
   }
@@ -67,6 +126,9 @@ impl ResponseError for AppError {
         .with_header(header::CACHE_CONTROL, "public, max-age=31536000")
         .with_header("Cache-Tag", "missing, missing-index")
         .into_response(),
+      AppError::Unauthorized => resp
+        .with_header(header::WWW_AUTHENTICATE, "Bearer")
+        .into_response(),
       _ => resp,
     }
   }
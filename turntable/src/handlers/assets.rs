@@ -0,0 +1,104 @@
+use mime_guess::mime;
+use poem::{
+  error::NotFoundError,
+  http::{header, StatusCode},
+  web::StaticFileRequest,
+  FromRequest, IntoResponse, Request, Response, Result,
+};
+
+use crate::{
+  config::Config,
+  utils::{encrypt::etag, get_content_type_header},
+};
+
+/// Assets built into the binary, each served at `/<name>`.
+pub const EMBEDDED_ASSETS: &[(&str, &[u8])] = &[
+  ("favicon.ico", include_bytes!("../../assets/favicon.ico")),
+  ("robots.txt", include_bytes!("../../assets/robots.txt")),
+];
+
+/// `GET /<name>` of an embedded asset, or of the file that replaces it in
+/// `assets.dir`.
+#[poem::handler]
+pub async fn serve_asset(req: &Request) -> Result<Response> {
+  let config = <&Config>::from_request_without_body(req).await?;
+  let name = req.uri().path().trim_start_matches('/');
+  let Some(&(name, content)) = EMBEDDED_ASSETS.iter().find(|(asset, _)| *asset == name) else {
+    return Err(NotFoundError.into());
+  };
+
+  if let Some(path) = config
+    .assets
+    .dir
+    .as_ref()
+    .map(|dir| dir.join(name))
+    .filter(|path| path.is_file())
+  {
+    let file = StaticFileRequest::from_request_without_body(req).await?;
+    return Ok(file.create_response(path, true)?.into_response());
+  }
+
+  let content_type = mime_guess::from_path(name).first_or(mime::APPLICATION_OCTET_STREAM);
+  Ok(
+    StatusCode::OK
+      .with_header(
+        header::CONTENT_TYPE,
+        get_content_type_header(content_type.as_ref()),
+      )
+      .with_header(header::ETAG, etag(content)?)
+      .with_body(content)
+      .into_response(),
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use poem::http::header;
+
+  use crate::{
+    config::Config,
+    registry::MemoryRegistry,
+    test_utils::{pkg_client, test_client_with},
+  };
+
+  #[tokio::test]
+  async fn test_assets() -> anyhow::Result<()> {
+    let cli = pkg_client().await?;
+    let resp = cli.get("/robots.txt").send().await;
+    resp.assert_status_is_ok();
+    resp.assert_header(header::CACHE_CONTROL, "public, max-age=31536000");
+    resp
+      .assert_text(include_str!("../../assets/robots.txt"))
+      .await;
+
+    let dir = tempfile::tempdir()?;
+    std::fs::write(dir.path().join("robots.txt"), "User-agent: *\nDisallow: /")?;
+    std::fs::create_dir(dir.path().join("well-known"))?;
+    std::fs::write(dir.path().join("well-known/security.txt"), "Contact: ops")?;
+    let config: Config = toml::from_str(&format!(
+      r#"
+        [assets]
+        dir = "{0}"
+
+        [[assets.routes]]
+        path = "/.well-known"
+        dir = "{0}/well-known"
+        cache_control = "no-cache"
+      "#,
+      dir.path().display()
+    ))?;
+    let cli = test_client_with(config, MemoryRegistry::new())?;
+
+    let resp = cli.get("/robots.txt").send().await;
+    resp.assert_status_is_ok();
+    resp.assert_text("User-agent: *\nDisallow: /").await;
+    let resp = cli.get("/favicon.ico").send().await;
+    resp.assert_status_is_ok();
+    resp.assert_content_type("image/x-icon");
+    let resp = cli.get("/.well-known/security.txt").send().await;
+    resp.assert_status_is_ok();
+    resp.assert_header(header::CACHE_CONTROL, "no-cache");
+    resp.assert_text("Contact: ops").await;
+    Ok(())
+  }
+}
@@ -0,0 +1,398 @@
+use std::{fmt::Write, path::PathBuf};
+
+use mime_guess::mime;
+use node_semver::Version;
+use poem::{
+  http::{header, StatusCode},
+  FromRequest, IntoResponse, Request, Response, Result,
+};
+
+use super::meta_dir::{find_matching_entries, get_metadata};
+use crate::{
+  errors::AppError,
+  models::{Metadata, PackageConfig, PackagePathname},
+  policy::Policy,
+  utils::{
+    escape_html,
+    npm::NpmClient,
+    redirect, strip_suffix_filename,
+    url::{create_pkg_url, mount_prefix},
+  },
+};
+
+/// Files larger than this are linked instead of shown.
+const MAX_VIEW_SIZE: usize = 1024 * 1024;
+
+const STYLE: &str = r#"
+body { margin: 0 auto; max-width: 960px; padding: 0 16px; font: 14px/1.5 system-ui, sans-serif; color: #24292f; }
+header { display: flex; align-items: center; justify-content: space-between; gap: 16px; padding: 16px 0; border-bottom: 1px solid #d0d7de; }
+h1 { margin: 0; font-size: 18px; font-weight: 600; word-break: break-all; }
+a { color: #0969da; text-decoration: none; }
+a:hover { text-decoration: underline; }
+table { width: 100%; border-collapse: collapse; }
+th, td { padding: 4px 8px; text-align: left; border-bottom: 1px solid #eaeef2; }
+td.size, th.size { text-align: right; white-space: nowrap; }
+.details { display: flex; justify-content: space-between; padding: 12px 0; color: #57606a; }
+.code { font: 12px/1.5 ui-monospace, monospace; }
+.code td { border: 0; padding: 0 8px; vertical-align: top; }
+.code td.num { width: 1%; text-align: right; user-select: none; }
+.code td.num a { color: #8c959f; }
+.code pre { margin: 0; white-space: pre-wrap; word-break: break-all; }
+.code tr:target { background: #fff8c5; }
+"#;
+
+/// Percent-encodes each segment of a file path, keeping the slashes.
+#[inline]
+fn encode_path(path: &str) -> String {
+  path
+    .split('/')
+    .map(|segment| urlencoding::encode(segment).into_owned())
+    .collect::<Vec<_>>()
+    .join("/")
+}
+
+fn format_size(size: u64) -> String {
+  match size {
+    size if size < 1000 => format!("{size} B"),
+    size if size < 1000 * 1000 => format!("{:.1} kB", size as f64 / 1000.0),
+    size => format!("{:.1} MB", size as f64 / 1000.0 / 1000.0),
+  }
+}
+
+/// The chrome shared by every browse page: breadcrumbs from the package root
+/// to `filename`, and a switcher that opens the same path in another version.
+struct Page<'a> {
+  prefix: &'a str,
+  pkg: &'a PackagePathname,
+  versions: Vec<String>,
+}
+
+impl Page<'_> {
+  fn url(&self, version: &str, filename: &str) -> String {
+    let url = create_pkg_url(&self.pkg.package_name, version, encode_path(filename), None);
+    format!("{}{url}", self.prefix)
+  }
+
+  fn breadcrumbs(&self, filename: &str) -> String {
+    let version = &self.pkg.package_version;
+    let mut html = format!(
+      r#"<a href="{}">{}</a>"#,
+      escape_html(&self.url(version, "/")),
+      escape_html(&self.pkg.package_spec)
+    );
+    let segments = filename
+      .trim_matches('/')
+      .split('/')
+      .filter(|segment| !segment.is_empty())
+      .collect::<Vec<_>>();
+    let mut path = String::new();
+    for (i, segment) in segments.iter().enumerate() {
+      path = format!("{path}/{segment}");
+      if i + 1 == segments.len() && !filename.ends_with('/') {
+        let _ = write!(html, " / {}", escape_html(segment));
+      } else {
+        let url = self.url(version, &format!("{path}/"));
+        let _ = write!(
+          html,
+          r#" / <a href="{}">{}</a>"#,
+          escape_html(&url),
+          escape_html(segment)
+        );
+      }
+    }
+    html
+  }
+
+  fn version_switcher(&self, filename: &str) -> String {
+    let options = self
+      .versions
+      .iter()
+      .map(|version| {
+        let selected = match *version == self.pkg.package_version {
+          true => " selected",
+          false => "",
+        };
+        format!(
+          r#"<option value="{}"{selected}>{}</option>"#,
+          escape_html(&self.url(version, filename)),
+          escape_html(version)
+        )
+      })
+      .collect::<String>();
+    format!(
+      r#"<select aria-label="Version" onchange="window.location.href = this.value">{options}</select>"#
+    )
+  }
+
+  fn render(&self, filename: &str, main: &str) -> Response {
+    let title = format!("{}{}", self.pkg.package_spec, filename);
+    let html = format!(
+      r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{}</title>
+<style>{STYLE}</style>
+</head>
+<body>
+<header><h1>{}</h1>{}</header>
+<main>{main}</main>
+</body>
+</html>
+"#,
+      escape_html(&title),
+      self.breadcrumbs(filename),
+      self.version_switcher(filename)
+    );
+
+    StatusCode::OK
+      .with_header(header::CONTENT_TYPE, mime::TEXT_HTML_UTF_8.as_ref())
+      .with_header(header::CACHE_CONTROL, "public, max-age=600")
+      .with_header("Cache-Tag", "browse")
+      .with_body(html)
+      .into_response()
+  }
+}
+
+fn render_directory(page: &Page, dirname: &str, files: &[Metadata]) -> Response {
+  let version = &page.pkg.package_version;
+  let mut rows = String::new();
+  if dirname != "/" {
+    let _ = write!(
+      rows,
+      r#"<tr><td><a href="../">../</a></td><td class="size">-</td><td>-</td></tr>"#
+    );
+  }
+
+  let (dirs, files): (Vec<_>, Vec<_>) = files
+    .iter()
+    .partition(|file| matches!(file, Metadata::Directory { .. }));
+  for entry in dirs.into_iter().chain(files) {
+    let (path, size, content_type) = match entry {
+      Metadata::Directory { path, .. } => (format!("{}/", path.display()), None, None),
+      Metadata::File {
+        path,
+        size,
+        content_type,
+        ..
+      } => (path.display().to_string(), Some(*size), Some(content_type)),
+    };
+    let name = path
+      .strip_prefix(dirname)
+      .unwrap_or(&path)
+      .trim_start_matches('/');
+    let _ = write!(
+      rows,
+      r#"<tr><td><a href="{}">{}</a></td><td class="size">{}</td><td>{}</td></tr>"#,
+      escape_html(&page.url(version, &path)),
+      escape_html(name),
+      size.map(format_size).unwrap_or_else(|| "-".into()),
+      content_type
+        .map(|ty| escape_html(ty.as_ref()))
+        .unwrap_or_else(|| "-".into())
+    );
+  }
+
+  let main = format!(
+    r#"<table><thead><tr><th>Name</th><th class="size">Size</th><th>Content type</th></tr></thead><tbody>{rows}</tbody></table>"#
+  );
+  page.render(&format!("{}/", dirname.trim_end_matches('/')), &main)
+}
+
+fn render_file(page: &Page, filename: &str, metadata: &Metadata, content: &[u8]) -> Response {
+  let Metadata::File {
+    size, content_type, ..
+  } = metadata
+  else {
+    unreachable!("a file view needs file metadata");
+  };
+  let raw_url = create_pkg_url(
+    &page.pkg.package_name,
+    &page.pkg.package_version,
+    encode_path(filename),
+    None,
+  );
+
+  let text = std::str::from_utf8(content)
+    .ok()
+    .filter(|text| !text.contains('\0'));
+  let body = match text {
+    Some(text) if content.len() <= MAX_VIEW_SIZE => {
+      let mut rows = String::new();
+      for (i, line) in text.lines().enumerate() {
+        let n = i + 1;
+        let _ = write!(
+          rows,
+          r##"<tr id="L{n}"><td class="num"><a href="#L{n}">{n}</a></td><td><pre>{}</pre></td></tr>"##,
+          escape_html(line)
+        );
+      }
+      format!(r#"<table class="code"><tbody>{rows}</tbody></table>"#)
+    }
+    Some(_) => "<p>This file is too large to display.</p>".to_owned(),
+    None => "<p>This is a binary file.</p>".to_owned(),
+  };
+
+  let main = format!(
+    r#"<div class="details"><span>{} &middot; {}</span><a href="{}">View raw</a></div>{body}"#,
+    format_size(*size),
+    escape_html(content_type.as_ref()),
+    escape_html(&raw_url)
+  );
+  page.render(filename, &main)
+}
+
+/// `GET /browse/:name@:version/:path`: an HTML listing for paths ending in
+/// `/`, and a viewer with line numbers for files.
+#[poem::handler]
+pub async fn browse_package(req: &Request) -> Result<Response> {
+  let npm = <&NpmClient>::from_request_without_body(req).await?;
+  let pkg = <&PackagePathname>::from_request_without_body(req).await?;
+  let package_config = <&PackageConfig>::from_request_without_body(req).await?;
+  let policy = <&Policy>::from_request_without_body(req).await?;
+  let prefix = mount_prefix(req);
+
+  let dir_redirect = |dirname: &str| {
+    let url = create_pkg_url(&pkg.package_name, &pkg.package_version, dirname, None);
+    redirect(format!("{prefix}{url}/")).into_response()
+  };
+  if pkg.filename.is_empty() {
+    return Ok(dir_redirect(""));
+  }
+
+  let index = npm
+    .get_package_index(
+      &pkg.package_name,
+      &pkg.package_version,
+      package_config.integrity(),
+    )
+    .await?;
+  let (versions_and_tags, _) = npm.get_versions_and_tags(&pkg.package_name).await?;
+  // The switcher offers only the versions a range could resolve to.
+  let mut versions = policy
+    .allowed_versions(npm, &pkg.package_name, versions_and_tags.versions)
+    .await?
+    .into_iter()
+    .filter_map(|version| Some((Version::parse(&version).ok()?, version)))
+    .collect::<Vec<_>>();
+  versions.sort_by(|(a, _), (b, _)| b.cmp(a));
+  let page = Page {
+    prefix,
+    pkg,
+    versions: versions.into_iter().map(|(_, version)| version).collect(),
+  };
+
+  let not_found = || AppError::NotFoundFileInPackage {
+    package_spec: pkg.package_spec.clone(),
+    filename: pkg.filename.clone(),
+  };
+
+  if pkg.filename.ends_with('/') {
+    let dirname = strip_suffix_filename(&pkg.filename);
+    if !index.is_dir(dirname) {
+      return Err(not_found().into());
+    }
+    let entries = find_matching_entries(&index, dirname)?;
+    let files = entries
+      .get(&PathBuf::from(dirname))
+      .map(|entry| get_metadata(entry.clone(), &entries));
+    return match files {
+      Some(Metadata::Directory { files, .. }) => Ok(render_directory(&page, dirname, &files)),
+      _ => Err(not_found().into()),
+    };
+  }
+
+  match index.file(&pkg.filename) {
+    Some(file) => Ok(render_file(
+      &page,
+      &pkg.filename,
+      &Metadata::try_from(file)?,
+      &index.content(file),
+    )),
+    None if index.is_dir(&pkg.filename) => Ok(dir_redirect(&pkg.filename)),
+    None => Err(not_found().into()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use poem::http::{header, StatusCode};
+
+  use crate::{
+    config::{Config, PolicyConfig, PolicyRule},
+    registry::MemoryRegistry,
+    test_utils::{insert_package, pkg_client, test_client_with},
+  };
+
+  #[tokio::test]
+  async fn test_browse() -> anyhow::Result<()> {
+    let cli = pkg_client().await?;
+
+    let resp = cli.get("/browse/pkg").send().await;
+    resp.assert_header(header::LOCATION, "/browse/pkg@1.0.0");
+    let resp = cli.get("/browse/pkg@1.0.0").send().await;
+    resp.assert_header(header::LOCATION, "/browse/pkg@1.0.0/");
+    let resp = cli.get("/browse/pkg@1.0.0/lib").send().await;
+    resp.assert_header(header::LOCATION, "/browse/pkg@1.0.0/lib/");
+
+    let resp = cli.get("/browse/pkg@1.0.0/").send().await;
+    resp.assert_status_is_ok();
+    resp.assert_content_type("text/html; charset=utf-8");
+    let html = resp.0.into_body().into_string().await?;
+    assert!(html.contains(r#"<a href="/browse/pkg@1.0.0/lib/">lib/</a>"#));
+    assert!(html.contains(r#"<a href="/browse/pkg@1.0.0/index.js">index.js</a>"#));
+    assert!(html.contains(r#"<option value="/browse/pkg@1.0.0/" selected>1.0.0</option>"#));
+
+    let resp = cli.get("/browse/pkg@1.0.0/lib/index.js").send().await;
+    resp.assert_status_is_ok();
+    let html = resp.0.into_body().into_string().await?;
+    assert!(html.contains(r#"<a href="/browse/pkg@1.0.0/lib/">lib</a> / index.js"#));
+    assert!(html.contains(r##"<a href="#L1">1</a></td><td><pre>export default 2;</pre>"##));
+    assert!(html.contains(r#"<a href="/pkg@1.0.0/lib/index.js">View raw</a>"#));
+
+    let resp = cli.get("/browse/pkg@1.0.0/missing/").send().await;
+    resp.assert_status(StatusCode::NOT_FOUND);
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_browse_hides_blocked_versions() -> anyhow::Result<()> {
+    let registry = MemoryRegistry::new();
+    for (version, license) in [
+      ("1.0.0", "MIT"),
+      ("1.1.0", "MIT"),
+      ("1.2.0", "MIT"),
+      ("1.3.0", "GPL-3.0-only"),
+    ] {
+      insert_package(
+        &registry,
+        serde_json::json!({ "name": "pkg", "version": version, "license": license }),
+        &[("/index.js", "export default 1;")],
+      )
+      .await?;
+    }
+    let config = Config {
+      policy: PolicyConfig {
+        deny: vec![PolicyRule {
+          package: "pkg".into(),
+          versions: Some("1.1.0".into()),
+          reason: None,
+        }],
+        licenses: vec!["MIT".into()],
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    let cli = test_client_with(config, registry)?;
+
+    let resp = cli.get("/browse/pkg@1.0.0/").send().await;
+    resp.assert_status_is_ok();
+    let html = resp.0.into_body().into_string().await?;
+    assert!(html.contains(r#"<option value="/browse/pkg@1.2.0/">1.2.0</option>"#));
+    assert!(html.contains(r#"<option value="/browse/pkg@1.0.0/" selected>1.0.0</option>"#));
+    assert!(!html.contains("1.1.0"));
+    assert!(!html.contains("1.3.0"));
+    Ok(())
+  }
+}
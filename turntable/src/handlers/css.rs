@@ -0,0 +1,75 @@
+use mime_guess::mime;
+use poem::{
+  http::{header, StatusCode},
+  FromRequest, IntoResponse, Request, Response, Result,
+};
+
+use crate::{
+  config::Config,
+  errors::AppError,
+  models::{Entry, PackageConfig},
+  utils::{css::rewrite_css, encrypt::etag},
+};
+
+/// `?css`: a stylesheet with its package references rewritten to urls.
+pub async fn serve_css(req: &Request) -> Result<Response> {
+  let config = <&Config>::from_request_without_body(req).await?;
+  let pkg_config = <&PackageConfig>::from_request_without_body(req).await?;
+  let entry = <&Entry>::from_request_without_body(req).await?;
+  if entry.content_type != mime::TEXT_CSS {
+    return Err(AppError::InvalidContentTypeForCssMode.into());
+  }
+
+  let css = rewrite_css(
+    &String::from_utf8_lossy(&entry.content),
+    &config.origin,
+    pkg_config,
+    None,
+  );
+  let resp = StatusCode::OK
+    .with_header(header::CONTENT_TYPE, mime::TEXT_CSS_UTF_8.as_ref())
+    .with_header(header::CACHE_CONTROL, "public, max-age=31536000")
+    .with_header(header::ETAG, etag(&css)?)
+    .with_header("Cache-Tag", "file, css-file, css-rewrite")
+    .with_body(css)
+    .into_response();
+  Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+  use poem::http::{header, StatusCode};
+
+  use crate::test_utils::test_client;
+
+  #[tokio::test]
+  async fn test_css() -> anyhow::Result<()> {
+    let cli = test_client(
+      serde_json::json!({
+        "name": "theme",
+        "version": "1.0.0",
+        "style": "dist/theme.css",
+        "dependencies": { "normalize.css": "^8.0.1" }
+      }),
+      &[
+        ("/index.js", "export {};"),
+        ("/dist/theme.css", r#"@import "normalize.css";"#),
+      ],
+    )
+    .await?;
+
+    let resp = cli.get("/theme@1.0.0?css").send().await;
+    resp.assert_header(header::LOCATION, "/theme@1.0.0/dist/theme.css?css");
+
+    let resp = cli.get("/theme@1.0.0/dist/theme.css?css").send().await;
+    resp.assert_status_is_ok();
+    resp.assert_content_type("text/css; charset=utf-8");
+    resp
+      .assert_text(r#"@import "https://unpkg.com/normalize.css@^8.0.1?css";"#)
+      .await;
+
+    let resp = cli.get("/theme@1.0.0/index.js?css").send().await;
+    resp.assert_status(StatusCode::FORBIDDEN);
+    Ok(())
+  }
+}
@@ -5,29 +5,164 @@ use poem::{
 
 use crate::{
   models::Entry,
-  utils::{encrypt::etag, get_content_type_header},
+  utils::{
+    get_content_type_header,
+    headers::{file_headers, if_range_matches},
+    range::{content_range, multipart_byteranges, parse_range},
+  },
 };
 
-pub async fn serve_file(req: &Request) -> poem::Result<Response> {
-  let entry = <&Entry>::from_request_without_body(req).await?.to_owned();
-
-  let mut tags = vec!["file"];
-  if let Some(ext) = entry.path.extension().and_then(|s| s.to_str()) {
-    tags.push(ext);
-  }
+/// `HEAD` of a file, from the index entry without its content. `Range` is
+/// ignored, as it is defined for `GET` only.
+pub async fn serve_file_head(req: &Request) -> poem::Result<Response> {
+  let entry = <&Entry>::from_request_without_body(req).await?;
 
-  let resp = StatusCode::OK
+  let resp = file_headers(entry)
     .with_header(
       header::CONTENT_TYPE,
-      get_content_type_header(entry.content_type),
+      get_content_type_header(&entry.content_type),
     )
     .with_header(header::CONTENT_LENGTH, format!("{}", entry.size))
-    .with_header(header::CACHE_CONTROL, "public, max-age=31536000")
-    .with_header(header::LAST_MODIFIED, entry.last_modified)
-    .with_header(header::ETAG, etag(&entry.content)?)
-    .with_header("Cache-Tag", tags.join(", "))
-    .with_body(entry.content)
     .into_response();
+  Ok(resp)
+}
+
+pub async fn serve_file(req: &Request) -> poem::Result<Response> {
+  let entry = <&Entry>::from_request_without_body(req).await?;
+
+  let content_type = get_content_type_header(&entry.content_type);
+  let len = entry.content.len() as u64;
+  let ranges = req
+    .headers()
+    .get(header::RANGE)
+    .and_then(|value| value.to_str().ok())
+    .filter(|_| if_range_matches(req, &entry.last_modified))
+    .and_then(|value| parse_range(value, len));
+
+  let resp = file_headers(entry);
+
+  let resp = match ranges.as_deref() {
+    None => resp
+      .with_header(header::CONTENT_TYPE, content_type)
+      .with_header(header::CONTENT_LENGTH, format!("{}", entry.size))
+      .with_body(entry.content.clone())
+      .into_response(),
+    Some([]) => resp
+      .with_status(StatusCode::RANGE_NOT_SATISFIABLE)
+      .with_header(header::CONTENT_RANGE, format!("bytes */{len}"))
+      .into_response(),
+    Some([range]) => {
+      let body = entry
+        .content
+        .slice(range.start as usize..range.end as usize);
+      resp
+        .with_status(StatusCode::PARTIAL_CONTENT)
+        .with_header(header::CONTENT_TYPE, content_type)
+        .with_header(header::CONTENT_RANGE, content_range(range, len))
+        .with_header(header::CONTENT_LENGTH, format!("{}", body.len()))
+        .with_body(body)
+        .into_response()
+    }
+    Some(ranges) => {
+      let boundary = entry
+        .etag
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect::<String>();
+      let body = multipart_byteranges(&entry.content, ranges, &content_type, &boundary);
+      resp
+        .with_status(StatusCode::PARTIAL_CONTENT)
+        .with_header(
+          header::CONTENT_TYPE,
+          format!("multipart/byteranges; boundary={boundary}"),
+        )
+        .with_header(header::CONTENT_LENGTH, format!("{}", body.len()))
+        .with_body(body)
+        .into_response()
+    }
+  };
 
   Ok(resp)
 }
+
+#[cfg(test)]
+mod tests {
+  use poem::http::{header, StatusCode};
+
+  use crate::test_utils::pkg_client;
+
+  #[tokio::test]
+  async fn test_range_requests() -> anyhow::Result<()> {
+    let cli = pkg_client().await?;
+    let path = "/pkg@1.0.0/index.js";
+
+    let resp = cli.get(path).send().await;
+    resp.assert_header(header::ACCEPT_RANGES, "bytes");
+    let etag = resp.0.header(header::ETAG).unwrap().to_owned();
+
+    let resp = cli
+      .get(path)
+      .header(header::RANGE, "bytes=7-13")
+      .header(header::ACCEPT_ENCODING, "gzip")
+      .send()
+      .await;
+    resp.assert_status(StatusCode::PARTIAL_CONTENT);
+    resp.assert_header(header::CONTENT_RANGE, "bytes 7-13/17");
+    resp.assert_text("default").await;
+
+    let resp = cli
+      .get(path)
+      .header(header::RANGE, "bytes=0-5,-2")
+      .send()
+      .await;
+    resp.assert_status(StatusCode::PARTIAL_CONTENT);
+    let content_type = resp.0.content_type().unwrap().to_owned();
+    let boundary = content_type
+      .strip_prefix("multipart/byteranges; boundary=")
+      .unwrap();
+    let body = resp.0.into_body().into_string().await?;
+    assert_eq!(body.matches(&format!("--{boundary}")).count(), 3);
+    assert!(body.contains("Content-Range: bytes 0-5/17\r\n\r\nexport\r\n"));
+    assert!(body.contains("Content-Range: bytes 15-16/17\r\n\r\n1;\r\n"));
+
+    let resp = cli
+      .get(path)
+      .header(header::RANGE, "bytes=100-")
+      .send()
+      .await;
+    resp.assert_status(StatusCode::RANGE_NOT_SATISFIABLE);
+    resp.assert_header(header::CONTENT_RANGE, "bytes */17");
+
+    let last_modified = resp.0.header(header::LAST_MODIFIED).unwrap().to_owned();
+    let resp = cli
+      .get(path)
+      .header(header::RANGE, "bytes=0-5")
+      .header(header::IF_RANGE, &last_modified)
+      .send()
+      .await;
+    resp.assert_status(StatusCode::PARTIAL_CONTENT);
+    // A weak ETag is no validator for `If-Range`, even when it matches.
+    for validator in [etag.as_str(), "W/\"other\""] {
+      let resp = cli
+        .get(path)
+        .header(header::RANGE, "bytes=0-5")
+        .header(header::IF_RANGE, validator)
+        .send()
+        .await;
+      resp.assert_status_is_ok();
+      resp.assert_text("export default 1;").await;
+    }
+
+    let resp = cli
+      .head(path)
+      .header(header::RANGE, "bytes=0-5")
+      .send()
+      .await;
+    resp.assert_status_is_ok();
+    resp.assert_header(header::CONTENT_LENGTH, "17");
+    resp.assert_header(header::ETAG, &etag);
+    resp.assert_header_is_not_exist(header::CONTENT_RANGE);
+    resp.assert_text("").await;
+    Ok(())
+  }
+}
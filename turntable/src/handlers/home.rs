@@ -0,0 +1,47 @@
+use mime_guess::mime;
+use poem::{
+  http::{header, StatusCode},
+  IntoResponse, Response, Result,
+};
+
+use crate::{config::Config, utils::escape_html};
+
+const HOME_HTML: &str = include_str!("../../assets/home.html");
+
+/// `GET /`: a landing page describing the URL scheme, with a box to browse
+/// a package.
+#[poem::handler]
+pub async fn homepage(config: &Config) -> Result<Response> {
+  let html = HOME_HTML
+    .replace("{{origin}}", &escape_html(&config.origin))
+    .replace("{{registry}}", &escape_html(&config.npm_registry_url));
+
+  Ok(
+    StatusCode::OK
+      .with_header(header::CONTENT_TYPE, mime::TEXT_HTML_UTF_8.as_ref())
+      .with_header(header::CACHE_CONTROL, "public, max-age=600")
+      .with_header("Cache-Tag", "home")
+      .with_body(html)
+      .into_response(),
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{config::Config, test_utils::pkg_client};
+
+  #[tokio::test]
+  async fn test_home() -> anyhow::Result<()> {
+    let cli = pkg_client().await?;
+
+    let resp = cli.get("/").send().await;
+    resp.assert_status_is_ok();
+    resp.assert_content_type("text/html; charset=utf-8");
+    let html = resp.0.into_body().into_string().await?;
+    assert!(html.contains(r#"<form id="browse">"#));
+    assert!(html.contains("<code>https://unpkg.com/:package@:version/:file</code>"));
+    assert!(html.contains(&Config::default().npm_registry_url));
+    assert!(!html.contains("{{"));
+    Ok(())
+  }
+}
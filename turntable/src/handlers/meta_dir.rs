@@ -1,82 +1,44 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{collections::BTreeMap, path::PathBuf};
 
-use async_compression::tokio::bufread::GzipDecoder;
-use bytes::Bytes;
-
-use poem::{web::Json, FromRequest, IntoResponse, Request, Response, Result};
-
-use tokio_stream::StreamExt;
-use tokio_tar::Archive;
+use poem::{FromRequest, IntoResponse, Request, Response, Result};
 
 use crate::{
-  models::{Metadata, Mtime, PackagePathname},
-  utils::{
-    encrypt::get_intergrity, fs::get_content_type, npm::get_package, read_entry_file,
-    strip_suffix_filename,
-  },
+  models::{Metadata, PackageConfig, PackageIndex, PackagePathname},
+  utils::{json_response, npm::NpmClient, strip_suffix_filename},
 };
 
-pub async fn find_matching_entries(
-  steam: Bytes,
+pub fn find_matching_entries(
+  index: &PackageIndex,
   filename: impl AsRef<str>,
-) -> anyhow::Result<HashMap<PathBuf, Metadata>> {
+) -> anyhow::Result<BTreeMap<PathBuf, Metadata>> {
   // filename = /some/dir/name
   let filename = filename.as_ref();
-  let mut matching_entries = HashMap::new();
+  let mut matching_entries = BTreeMap::new();
   let file_path = PathBuf::from(filename);
   matching_entries.insert(file_path.clone(), Metadata::new_dir(filename));
 
-  let tar = GzipDecoder::new(&*steam);
-  let mut ar = Archive::new(tar);
-
-  let mut entries = ar.entries()?;
-  while let Some(Ok(mut file)) = entries.next().await {
-    // Make sure there wasn't an I/O error
-    let mut path = file.path()?.to_path_buf();
-
-    if !path.starts_with("/") {
-      path = PathBuf::from("/").join(path.iter().skip(1).collect::<PathBuf>());
-    }
-
-    for p in path.ancestors() {
-      let dir = match p.parent().and_then(|d| d.to_str()) {
-        Some(dir) if dir != "/" => dir,
-        _ => break,
-      };
+  for file in index.files_in(filename) {
+    let path = PathBuf::from(&file.path);
 
-      if dir.starts_with(filename) {
-        matching_entries.insert(
-          dir.into(),
-          Metadata::Directory {
-            path: PathBuf::from(dir),
-            files: vec![],
-          },
-        );
+    for dir in path.ancestors().skip(1) {
+      if dir == file_path {
+        break;
       }
+      matching_entries
+        .entry(dir.to_path_buf())
+        .or_insert_with(|| Metadata::Directory {
+          path: dir.to_path_buf(),
+          files: vec![],
+        });
     }
 
-    if !file.header().entry_type().is_file() || !path.starts_with(filename) {
-      continue;
-    }
-
-    let content = read_entry_file(&mut file).await?;
-
-    matching_entries.insert(
-      path.clone(),
-      Metadata::File {
-        content_type: get_content_type(&path),
-        path,
-        integrity: get_intergrity(&content)?,
-        last_modified: Mtime::from(file.header().mtime()?).try_into()?,
-        size: file.header().size()?,
-      },
-    );
+    matching_entries.insert(path, Metadata::try_from(file)?);
   }
 
   Ok(matching_entries)
 }
 
-fn get_metadata(mut entry: Metadata, entries: &HashMap<PathBuf, Metadata>) -> Metadata {
+pub fn get_metadata(mut entry: Metadata, entries: &BTreeMap<PathBuf, Metadata>) -> Metadata {
   if let Metadata::Directory { files, path, .. } = &mut entry {
     *files = entries
       .iter()
@@ -88,18 +50,59 @@ fn get_metadata(mut entry: Metadata, entries: &HashMap<PathBuf, Metadata>) -> Me
 }
 
 pub async fn serve_directory_metadata(req: &Request) -> Result<Response> {
+  let npm = <&NpmClient>::from_request_without_body(req).await?;
   let pkg = <&PackagePathname>::from_request_without_body(req).await?;
-  let stream = get_package(&pkg.package_name, &pkg.package_version).await?;
+  let package_config = <&PackageConfig>::from_request_without_body(req).await?;
+  let index = npm
+    .get_package_index(
+      &pkg.package_name,
+      &pkg.package_version,
+      package_config.integrity(),
+    )
+    .await?;
 
   let filename = strip_suffix_filename(&pkg.filename);
-  let entries = find_matching_entries(stream, filename).await?;
+  let entries = find_matching_entries(&index, filename)?;
   let metadata = entries
     .get(&PathBuf::from(filename))
     .map(|entry| get_metadata(entry.clone(), &entries));
 
   let resp = match metadata {
-    Some(entry) => Json(entry).into_response(),
+    Some(entry) => json_response(&entry)?,
     None => ().into_response(),
   };
   Ok(resp)
 }
+
+#[cfg(test)]
+mod tests {
+  use crate::test_utils::pkg_client;
+
+  #[tokio::test]
+  async fn test_serve_metadata() -> anyhow::Result<()> {
+    let cli = pkg_client().await?;
+
+    let resp = cli.get("/pkg@1.0.0/lib/index.js?meta").send().await;
+    resp.assert_status_is_ok();
+    let json = resp.json().await;
+    json
+      .value()
+      .object()
+      .get("path")
+      .assert_string("/lib/index.js");
+    json.value().object().get("size").assert_i64(17);
+
+    let resp = cli.get("/pkg@1.0.0/?meta").send().await;
+    resp.assert_status_is_ok();
+    let json = resp.json().await;
+    let root = json.value().object();
+    root.get("path").assert_string("/");
+    let files = root.get("files").array();
+    files.assert_len(2);
+    files.get(0).object().get("path").assert_string("/index.js");
+    let lib = files.get(1).object();
+    lib.get("type").assert_string("directory");
+    lib.get("files").array().assert_len(1);
+    Ok(())
+  }
+}
@@ -1,18 +1,33 @@
+mod assets;
+mod browse;
+mod css;
 mod file;
+mod home;
 mod meta_dir;
 mod meta_file;
 mod module;
+mod publish;
+mod registry;
 
-use poem::{Request, Response};
+use poem::{http::Method, Request, Response};
 
 use crate::{
   handlers::{
-    file::serve_file, meta_dir::serve_directory_metadata, meta_file::serve_file_metadata,
+    css::serve_css,
+    file::{serve_file, serve_file_head},
+    meta_dir::serve_directory_metadata,
+    meta_file::serve_file_metadata,
     module::serve_module,
   },
   models::PackageQuery,
 };
 
+pub use assets::{serve_asset, EMBEDDED_ASSETS};
+pub use browse::browse_package;
+pub use home::homepage;
+pub use publish::publish_package;
+pub use registry::{serve_packument, serve_tarball};
+
 #[poem::handler]
 pub async fn handle_pkg_pathname(query: PackageQuery, req: &Request) -> poem::Result<Response> {
   if query.meta.is_some() {
@@ -22,9 +37,49 @@ pub async fn handle_pkg_pathname(query: PackageQuery, req: &Request) -> poem::Re
     };
   }
 
+  if query.css.is_some() {
+    return serve_css(req).await;
+  }
+
   if query.module.is_some() {
     return serve_module(req).await;
   }
 
+  if req.method() == Method::HEAD {
+    return serve_file_head(req).await;
+  }
+
   serve_file(req).await
 }
+
+#[cfg(test)]
+mod tests {
+  use poem::http::{header, StatusCode};
+
+  use crate::test_utils::pkg_client;
+
+  #[tokio::test]
+  async fn test_serve_from_memory_registry() -> anyhow::Result<()> {
+    let cli = pkg_client().await?;
+
+    let resp = cli.get("/pkg").send().await;
+    resp.assert_status(StatusCode::FOUND);
+    resp.assert_header(header::LOCATION, "/pkg@1.0.0");
+
+    let resp = cli.get("/pkg@1.0.0").send().await;
+    resp.assert_status(StatusCode::FOUND);
+    resp.assert_header(header::LOCATION, "/pkg@1.0.0/index.js");
+
+    let resp = cli.get("/pkg@1.0.0/index.js").send().await;
+    resp.assert_status_is_ok();
+    resp.assert_text("export default 1;").await;
+
+    let resp = cli.get("/pkg@1.0.0/lib").send().await;
+    resp.assert_status(StatusCode::FOUND);
+    resp.assert_header(header::LOCATION, "/pkg@1.0.0/lib/index.js");
+
+    let resp = cli.get("/pkg@1.0.0/missing.js").send().await;
+    resp.assert_status(StatusCode::NOT_FOUND);
+    Ok(())
+  }
+}
@@ -3,51 +3,368 @@ use poem::{
   http::{header, StatusCode},
   FromRequest, IntoResponse, Request, Response, Result,
 };
+use reqwest::Url;
 
 use crate::{
+  config::Config,
   errors::AppError,
-  models::{Entry, PackageConfig, PackagePathname},
-  utils::{encrypt::etag, swc::rewrite_javascript_esmodule},
+  models::{Entry, PackageConfig, PackageIndex, PackagePathname},
+  utils::{
+    cjs::{analyze_commonjs, is_commonjs, resolve_relative, wrap_commonjs, CommonJsModule},
+    css::rewrite_css,
+    encrypt::etag,
+    html::rewrite_html_module,
+    npm::NpmClient,
+    swc::{rewrite_javascript_esmodule, transpile_module, Dialect},
+  },
 };
 
+/// How many files of re-exports are followed to find named exports.
+const MAX_REEXPORT_DEPTH: usize = 4;
+
 pub async fn serve_module(req: &Request) -> Result<Response> {
   let entry = <&Entry>::from_request_without_body(req).await?;
-  let pkg = <&PackagePathname>::from_request_without_body(req).await?;
   if entry.content_type == mime::APPLICATION_JAVASCRIPT {
-    return serve_javascript_module(req).await.map_err(|_| {
-      AppError::UnableGenerateModule {
-        package_spec: pkg.package_spec.to_owned(),
-        filename: pkg.filename.to_owned(),
-      }
-      .into()
-    });
+    serve_javascript_module(req).await
+  } else if let Some(dialect) = Dialect::from_content_type(&entry.content_type) {
+    serve_transpiled_module(req, dialect).await
+  } else if entry.content_type == mime::TEXT_HTML {
+    serve_html_module(req).await
+  } else if entry.content_type == mime::APPLICATION_JSON {
+    serve_json_module(req).await
+  } else if entry.content_type == mime::TEXT_CSS {
+    serve_css_module(req).await
+  } else {
+    Err(AppError::InvalidContentTypeForModuleMode.into())
   }
+}
 
-  if entry.content_type == mime::TEXT_HTML {
-    //TODO: html module
+/// The error for a file that cannot be parsed or transformed. Errors of
+/// fetching packages are returned as they are, so they keep their status.
+fn unable_to_generate<E>(pkg: &PackagePathname) -> impl FnOnce(E) -> AppError + '_ {
+  move |_| AppError::UnableGenerateModule {
+    package_spec: pkg.package_spec.to_owned(),
+    filename: pkg.filename.to_owned(),
   }
+}
 
-  Err(AppError::InvalidContentTypeForModuleMode).map_err(Into::into)
+#[inline]
+fn javascript_response(code: String, cache_tag: &str) -> anyhow::Result<Response> {
+  let resp = StatusCode::OK
+    .with_header(
+      header::CONTENT_TYPE,
+      mime::APPLICATION_JAVASCRIPT_UTF_8.as_ref(),
+    )
+    .with_header(header::CACHE_CONTROL, "public, max-age=31536000")
+    .with_header(header::ETAG, etag(&code)?)
+    .with_header("Cache-Tag", cache_tag)
+    .with_body(code)
+    .into_response();
+  Ok(resp)
 }
 
 async fn serve_javascript_module(req: &Request) -> poem::Result<Response> {
+  let config = <&Config>::from_request_without_body(req).await?;
   let pkg_config = <&PackageConfig>::from_request_without_body(req).await?;
   let entry = <&Entry>::from_request_without_body(req).await?.to_owned();
 
+  let pkg = <&PackagePathname>::from_request_without_body(req).await?;
+
   let code = match String::from_utf8(entry.content.to_vec()).ok() {
-    Some(code) => rewrite_javascript_esmodule(code, pkg_config)?,
+    Some(code) if is_commonjs(&pkg.filename, &code, pkg_config) => {
+      let npm = <&NpmClient>::from_request_without_body(req).await?;
+      let index = npm
+        .get_package_index(
+          &pkg.package_name,
+          &pkg.package_version,
+          pkg_config.integrity(),
+        )
+        .await?;
+      let code = regex!(r"^#![^\n]*").replace(&code, "");
+      let module = analyze_commonjs(&code);
+      let exports = commonjs_exports(&index, &pkg.filename, &module, MAX_REEXPORT_DEPTH);
+      let code = wrap_commonjs(&code, &pkg.filename, &module.requires, &exports);
+      let code = rewrite_javascript_esmodule(code, &config.origin, pkg_config)
+        .map_err(unable_to_generate(pkg))?;
+      return Ok(javascript_response(
+        code,
+        "file, js-file, js-module, cjs-module",
+      )?);
+    }
+    Some(code) => rewrite_javascript_esmodule(code, &config.origin, pkg_config)
+      .map_err(unable_to_generate(pkg))?,
     None => String::default(),
   };
-  //
+  Ok(javascript_response(code, "file, js-file, js-module")?)
+}
+
+/// `.ts`, `.tsx` and `.jsx` files compiled to JavaScript, with `config.jsx`
+/// for the JSX runtime.
+async fn serve_transpiled_module(req: &Request, dialect: Dialect) -> poem::Result<Response> {
+  let config = <&Config>::from_request_without_body(req).await?;
+  let pkg_config = <&PackageConfig>::from_request_without_body(req).await?;
+  let pkg = <&PackagePathname>::from_request_without_body(req).await?;
+  let entry = <&Entry>::from_request_without_body(req).await?;
+
+  let code = String::from_utf8(entry.content.to_vec()).map_err(unable_to_generate(pkg))?;
+  let code = transpile_module(code, dialect, &config.jsx, &config.origin, pkg_config)
+    .map_err(unable_to_generate(pkg))?;
+  Ok(javascript_response(
+    code,
+    "file, js-file, js-module, transpiled-module",
+  )?)
+}
+
+/// The named exports of a CommonJS module, with those of the files of the
+/// package it re-exports.
+fn commonjs_exports(
+  index: &PackageIndex,
+  filename: &str,
+  module: &CommonJsModule,
+  depth: usize,
+) -> Vec<String> {
+  let mut exports = module.exports.clone();
+  if depth == 0 {
+    return exports;
+  }
+  for specifier in &module.reexports {
+    let Some(path) = resolve_relative(filename, specifier) else {
+      continue;
+    };
+    let Some((path, file)) = [
+      path.clone(),
+      format!("{path}.js"),
+      format!("{path}/index.js"),
+    ]
+    .into_iter()
+    .find_map(|path| index.file(&path).map(|file| (path, file))) else {
+      continue;
+    };
+    let content = index.content(file);
+    let Ok(code) = std::str::from_utf8(&content) else {
+      continue;
+    };
+    let reexported = analyze_commonjs(code);
+    for name in commonjs_exports(index, &path, &reexported, depth - 1) {
+      if !exports.contains(&name) {
+        exports.push(name);
+      }
+    }
+  }
+  exports
+}
+
+async fn serve_html_module(req: &Request) -> poem::Result<Response> {
+  let config = <&Config>::from_request_without_body(req).await?;
+  let pkg_config = <&PackageConfig>::from_request_without_body(req).await?;
+  let pkg = <&PackagePathname>::from_request_without_body(req).await?;
+  let entry = <&Entry>::from_request_without_body(req).await?;
+
+  let html = std::str::from_utf8(&entry.content).map_err(unable_to_generate(pkg))?;
+  let html =
+    rewrite_html_module(html, &config.origin, pkg_config).map_err(unable_to_generate(pkg))?;
   let resp = StatusCode::OK
-    .with_header(
-      header::CONTENT_TYPE,
-      mime::APPLICATION_JAVASCRIPT_UTF_8.as_ref(),
-    )
+    .with_header(header::CONTENT_TYPE, mime::TEXT_HTML_UTF_8.as_ref())
     .with_header(header::CACHE_CONTROL, "public, max-age=31536000")
-    .with_header(header::ETAG, etag(&code)?)
-    .with_header("Cache-Tag", "file, js-file, js-module")
-    .with_body(code)
+    .with_header(header::ETAG, etag(&html)?)
+    .with_header("Cache-Tag", "file, html-file, html-module")
+    .with_body(html)
     .into_response();
   Ok(resp)
 }
+
+/// `export default` of the parsed document, re-serialized so anything that is
+/// not JSON never reaches the browser as code.
+async fn serve_json_module(req: &Request) -> poem::Result<Response> {
+  let pkg = <&PackagePathname>::from_request_without_body(req).await?;
+  let entry = <&Entry>::from_request_without_body(req).await?;
+
+  let json =
+    serde_json::from_slice::<serde_json::Value>(&entry.content).map_err(unable_to_generate(pkg))?;
+  let code = format!("export default {json};\n");
+  Ok(javascript_response(code, "file, json-file, json-module")?)
+}
+
+/// A constructable `CSSStyleSheet` as the default export. It is not loaded
+/// from the package, so relative urls are resolved against the file's url.
+async fn serve_css_module(req: &Request) -> poem::Result<Response> {
+  let config = <&Config>::from_request_without_body(req).await?;
+  let pkg_config = <&PackageConfig>::from_request_without_body(req).await?;
+  let pkg = <&PackagePathname>::from_request_without_body(req).await?;
+  let entry = <&Entry>::from_request_without_body(req).await?;
+
+  let base_url = Url::parse(&format!(
+    "{}/{}{}",
+    config.origin, pkg.package_spec, pkg.filename
+  ))
+  .map_err(unable_to_generate(pkg))?;
+  let css = rewrite_css(
+    &String::from_utf8_lossy(&entry.content),
+    &config.origin,
+    pkg_config,
+    Some(&base_url),
+  );
+  let code = format!(
+    "const sheet = new CSSStyleSheet();\nsheet.replaceSync({});\nexport default sheet;\n",
+    serde_json::to_string(&css).map_err(unable_to_generate(pkg))?
+  );
+  Ok(javascript_response(code, "file, css-file, css-module")?)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::Arc;
+
+  use poem::{
+    http::{header, StatusCode},
+    Request,
+  };
+
+  use super::serve_module;
+  use crate::{
+    config::Config,
+    models::{Entry, PackageConfig, PackagePathname},
+    registry::MemoryRegistry,
+    test_utils::test_client,
+    utils::npm::NpmClient,
+  };
+
+  #[tokio::test]
+  async fn test_json_and_css_modules() -> anyhow::Result<()> {
+    let cli = test_client(
+      serde_json::json!({ "name": "pkg", "version": "1.0.0" }),
+      &[
+        ("/data.json", r#"{ "a": [1, 2] }"#),
+        ("/broken.json", "{"),
+        (
+          "/dist/style.css",
+          r#".logo { background: url("../logo.svg") }"#,
+        ),
+      ],
+    )
+    .await?;
+
+    let resp = cli.get("/pkg@1.0.0/data.json?module").send().await;
+    resp.assert_status_is_ok();
+    resp.assert_content_type("application/javascript; charset=utf-8");
+    resp.assert_header("Cache-Tag", "file, json-file, json-module");
+    resp.assert_text("export default {\"a\":[1,2]};\n").await;
+
+    let resp = cli.get("/pkg@1.0.0/broken.json?module").send().await;
+    resp.assert_status(StatusCode::INTERNAL_SERVER_ERROR);
+    resp
+      .assert_text("Cannot generate module for pkg@1.0.0/broken.json")
+      .await;
+
+    let resp = cli.get("/pkg@1.0.0/dist/style.css?module").send().await;
+    resp.assert_status_is_ok();
+    resp.assert_content_type("application/javascript; charset=utf-8");
+    resp.assert_header("Cache-Tag", "file, css-file, css-module");
+    resp
+      .assert_text(concat!(
+        "const sheet = new CSSStyleSheet();\n",
+        r#"sheet.replaceSync(".logo { background: url(\"https://unpkg.com/pkg@1.0.0/logo.svg\") }");"#,
+        "\nexport default sheet;\n"
+      ))
+      .await;
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_commonjs_module() -> anyhow::Result<()> {
+    let cli = test_client(
+      serde_json::json!({
+        "name": "cjs",
+        "version": "1.0.0",
+        "main": "index.js",
+        "dependencies": { "dep": "^1.0.0" }
+      }),
+      &[
+        (
+          "/index.js",
+          "module.exports = require('./lib');\nmodule.exports.dep = require('dep');",
+        ),
+        ("/lib/index.js", "exports.a = 1;\nexports.b = 2;"),
+      ],
+    )
+    .await?;
+
+    let resp = cli.get("/cjs@1.0.0?module").send().await;
+    resp.assert_header(header::LOCATION, "/cjs@1.0.0/index.js?module");
+
+    let resp = cli.get("/cjs@1.0.0/index.js?module").send().await;
+    resp.assert_status_is_ok();
+    resp.assert_header("Cache-Tag", "file, js-file, js-module, cjs-module");
+    let code = resp.0.into_body().into_string().await?;
+    assert!(code.contains(r#"from "./lib?module""#));
+    assert!(code.contains(r#"from "https://unpkg.com/dep@^1.0.0?module""#));
+    assert!(code.contains("export default __cjs_exports;"));
+    assert!(code.contains("__cjs_export1 as a"));
+    assert!(code.contains("__cjs_export2 as b"));
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_transpiled_module() -> anyhow::Result<()> {
+    let cli = test_client(
+      serde_json::json!({
+        "name": "ts",
+        "version": "1.0.0",
+        "dependencies": { "dep": "^1.0.0" }
+      }),
+      &[
+        (
+          "/index.ts",
+          r#"import dep from "dep"; export const a: number = dep;"#,
+        ),
+        ("/app.tsx", "export const App = () => <div />;"),
+      ],
+    )
+    .await?;
+
+    let resp = cli.get("/ts@1.0.0/index.ts").send().await;
+    resp.assert_content_type("application/typescript");
+    let resp = cli.get("/ts@1.0.0/app.tsx").send().await;
+    resp.assert_content_type("text/tsx");
+
+    let resp = cli.get("/ts@1.0.0/index.ts?module").send().await;
+    resp.assert_status_is_ok();
+    resp.assert_content_type("application/javascript; charset=utf-8");
+    resp.assert_header("Cache-Tag", "file, js-file, js-module, transpiled-module");
+    let code = resp.0.into_body().into_string().await?;
+    assert!(code.contains(r#"from "https://unpkg.com/dep@^1.0.0?module""#));
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_module_keeps_fetch_errors() -> anyhow::Result<()> {
+    let config = Config {
+      offline: true,
+      ..Default::default()
+    };
+    let npm = NpmClient::new(Arc::new(MemoryRegistry::new()), &config)?;
+    let mut req = Request::builder()
+      .uri_str("/cjs@1.0.0/index.js?module")
+      .finish();
+    req.extensions_mut().insert(Arc::new(config));
+    req.extensions_mut().insert(Arc::new(npm));
+    req.extensions_mut().insert(PackageConfig::default());
+    req.extensions_mut().insert(PackagePathname {
+      package_name: "cjs".into(),
+      package_version: "1.0.0".into(),
+      package_spec: "cjs@1.0.0".into(),
+      filename: "/index.js".into(),
+    });
+    req.extensions_mut().insert(Entry {
+      path: "/index.js".into(),
+      content_type: mime_guess::mime::APPLICATION_JAVASCRIPT,
+      content: "module.exports = require('./lib');".into(),
+      ..Default::default()
+    });
+
+    // Following the re-exports needs the package index, which is not cached.
+    let err = serve_module(&req).await.unwrap_err();
+    assert_eq!(err.status(), StatusCode::SERVICE_UNAVAILABLE);
+    Ok(())
+  }
+}
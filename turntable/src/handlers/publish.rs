@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+
+use base64::Engine;
+use node_semver::Version;
+use poem::{
+  error::ReadBodyError,
+  http::{header, StatusCode},
+  web::Json,
+  Body, FromRequest, IntoResponse, Request, Response, Result,
+};
+use serde::Deserialize;
+
+use crate::{
+  config::{Config, PublishConfig},
+  errors::AppError,
+  models::PackageConfig,
+  registry::tarball_filename,
+  utils::{
+    encrypt::{base64, verify_integrity},
+    npm::NpmClient,
+  },
+};
+
+/// The document `npm publish` sends, with the tarball base64 encoded in
+/// `_attachments`.
+#[derive(Debug, Deserialize)]
+pub struct PublishBody {
+  name: String,
+  #[serde(default)]
+  versions: HashMap<String, PackageConfig>,
+  #[serde(default, rename = "dist-tags")]
+  dist_tags: HashMap<String, String>,
+  #[serde(default, rename = "_attachments")]
+  attachments: HashMap<String, Attachment>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Attachment {
+  data: String,
+}
+
+/// Compares every byte so the time taken does not reveal a matching prefix.
+#[inline]
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+  a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+fn authorize(req: &Request, config: &PublishConfig) -> Result<(), AppError> {
+  let token = req
+    .headers()
+    .get(header::AUTHORIZATION)
+    .and_then(|value| value.to_str().ok())
+    .and_then(|value| value.strip_prefix("Bearer "))
+    .ok_or(AppError::Unauthorized)?;
+
+  match config
+    .tokens
+    .iter()
+    .any(|t| constant_time_eq(t.expose().as_bytes(), token.as_bytes()))
+  {
+    true => Ok(()),
+    false => Err(AppError::Unauthorized),
+  }
+}
+
+/// `PUT /:name`, as sent by `npm publish --registry`. Scoped names arrive
+/// encoded like `/@scope%2fname`. The body, with the tarball base64 encoded,
+/// may be at most `limits.max_tarball_size` bytes.
+#[poem::handler]
+pub async fn publish_package(req: &Request, body: Body) -> Result<Response> {
+  let config = <&Config>::from_request_without_body(req).await?;
+  let npm = <&NpmClient>::from_request_without_body(req).await?;
+  authorize(req, &config.publish)?;
+
+  let package_name = urlencoding::decode(req.uri().path().trim_start_matches('/'))
+    .map_err(|_| AppError::InvalidURL(req.uri().path().to_owned()))?
+    .into_owned();
+  validate_npm_package_name::validate(&package_name).map_err(|e| AppError::InvalidPackageName {
+    package_name: package_name.clone(),
+    reason: e.to_owned(),
+  })?;
+  let limit = config.limits.max_tarball_size;
+  let body = match body.into_bytes_limit(limit as usize).await {
+    Err(ReadBodyError::PayloadTooLarge) => {
+      return Err(
+        AppError::TarballTooLarge {
+          package_spec: package_name,
+          limit,
+        }
+        .into(),
+      )
+    }
+    result => result?,
+  };
+  let body: PublishBody = serde_json::from_slice(&body)
+    .map_err(|e| AppError::InvalidPublish(format!("invalid body: {e}")))?;
+  if body.name != package_name {
+    return Err(
+      AppError::InvalidPublish(format!(
+        "package name \"{}\" does not match the url",
+        body.name
+      ))
+      .into(),
+    );
+  }
+
+  let mut versions = body.versions.into_iter();
+  let (Some((version, package_config)), None) = (versions.next(), versions.next()) else {
+    return Err(AppError::InvalidPublish("expected exactly one version".into()).into());
+  };
+  // The version names the tarball file, so only a canonical semver may reach
+  // the file system.
+  let canonical = Version::parse(&version).is_ok_and(|v| v.to_string() == version);
+  if !canonical || package_config.get_str("version") != Some(version.as_str()) {
+    return Err(AppError::InvalidPublish(format!("invalid version \"{version}\"")).into());
+  }
+
+  let tarball = body
+    .attachments
+    .get(&tarball_filename(&package_name, &version))
+    .or_else(|| body.attachments.values().next())
+    .and_then(|attachment| base64.decode(&attachment.data).ok())
+    .ok_or_else(|| AppError::InvalidPublish("missing tarball".into()))?;
+  match package_config.integrity() {
+    Some(integrity) if verify_integrity(&tarball, &integrity) => {}
+    _ => {
+      return Err(AppError::InvalidPublish("tarball does not match dist.integrity".into()).into())
+    }
+  }
+
+  npm
+    .publish(
+      &package_name,
+      &version,
+      package_config,
+      body.dist_tags,
+      &tarball,
+    )
+    .await?;
+  tracing::info!("Published {package_name}@{version}");
+
+  Ok(
+    Json(serde_json::json!({ "ok": true }))
+      .with_status(StatusCode::CREATED)
+      .into_response(),
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use poem::http::{header, StatusCode};
+
+  use crate::{
+    config::{Config, LimitsConfig, PublishConfig},
+    registry::{create_tarball, MemoryRegistry},
+    test_utils::test_client_with,
+    utils::encrypt::{base64, get_intergrity},
+  };
+
+  #[tokio::test]
+  async fn test_publish() -> anyhow::Result<()> {
+    use base64::Engine;
+
+    let dir = tempfile::tempdir()?;
+    let config = Config {
+      publish: PublishConfig {
+        dir: Some(dir.path().to_path_buf()),
+        tokens: vec!["s3cret".to_owned().into()],
+      },
+      ..Default::default()
+    };
+    let cli = test_client_with(config, MemoryRegistry::new())?;
+
+    let tarball = create_tarball(&[("/dist/index.js", "export default 1;")]).await?;
+    let body = serde_json::json!({
+      "name": "@ourco/widget",
+      "dist-tags": { "latest": "1.2.0" },
+      "versions": {
+        "1.2.0": {
+          "name": "@ourco/widget",
+          "version": "1.2.0",
+          "dist": { "integrity": get_intergrity(&tarball)? }
+        }
+      },
+      "_attachments": {
+        "widget-1.2.0.tgz": { "data": base64.encode(&tarball), "length": tarball.len() }
+      }
+    });
+
+    let resp = cli.put("/@ourco%2fwidget").body_json(&body).send().await;
+    resp.assert_status(StatusCode::UNAUTHORIZED);
+
+    let publish = || {
+      cli
+        .put("/@ourco%2fwidget")
+        .header(header::AUTHORIZATION, "Bearer s3cret")
+        .body_json(&body)
+    };
+    publish().send().await.assert_status(StatusCode::CREATED);
+    publish().send().await.assert_status(StatusCode::CONFLICT);
+
+    let resp = cli.get("/@ourco/widget@1.2.0/dist/index.js").send().await;
+    resp.assert_status_is_ok();
+    resp.assert_text("export default 1;").await;
+
+    let resp = cli.get("/@ourco/widget/dist/index.js").send().await;
+    resp.assert_header(header::LOCATION, "/@ourco/widget@1.2.0/dist/index.js");
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_publish_rejects_invalid_requests() -> anyhow::Result<()> {
+    use base64::Engine;
+
+    let dir = tempfile::tempdir()?;
+    let publish_dir = dir.path().join("packages");
+    let config = Config {
+      publish: PublishConfig {
+        dir: Some(publish_dir.clone()),
+        tokens: vec!["s3cret".to_owned().into()],
+      },
+      limits: LimitsConfig {
+        max_tarball_size: 4096,
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    let cli = test_client_with(config, MemoryRegistry::new())?;
+
+    let tarball = create_tarball(&[("/index.js", "export default 1;")]).await?;
+    let body = |version: &str, manifest_version: &str, latest: &str| {
+      serde_json::json!({
+        "name": "pkg",
+        "dist-tags": { "latest": latest },
+        "versions": {
+          version: {
+            "name": "pkg",
+            "version": manifest_version,
+            "dist": { "integrity": get_intergrity(&tarball).unwrap() }
+          }
+        },
+        "_attachments": {
+          "pkg.tgz": { "data": base64.encode(&tarball), "length": tarball.len() }
+        }
+      })
+    };
+    let publish = |body: serde_json::Value| {
+      cli
+        .put("/pkg")
+        .header(header::AUTHORIZATION, "Bearer s3cret")
+        .body_json(&body)
+        .send()
+    };
+
+    let evil = "1.0.0/../../../evil";
+    publish(body(evil, evil, evil))
+      .await
+      .assert_status(StatusCode::BAD_REQUEST);
+    publish(body("1.0.0", "1.0.1", "1.0.0"))
+      .await
+      .assert_status(StatusCode::BAD_REQUEST);
+    publish(body("1.0.0", "1.0.0", "2.0.0"))
+      .await
+      .assert_status(StatusCode::BAD_REQUEST);
+    assert_eq!(std::fs::read_dir(dir.path())?.count(), 0);
+
+    let resp = cli
+      .put("/pkg")
+      .header(header::AUTHORIZATION, "Bearer s3cret")
+      .body(vec![b' '; 4097])
+      .send()
+      .await;
+    resp.assert_status(StatusCode::PAYLOAD_TOO_LARGE);
+
+    publish(body("1.0.0", "1.0.0", "1.0.0"))
+      .await
+      .assert_status(StatusCode::CREATED);
+    Ok(())
+  }
+}
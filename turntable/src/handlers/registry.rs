@@ -0,0 +1,150 @@
+use poem::{
+  http::{header, StatusCode},
+  web::Json,
+  FromRequest, IntoResponse, Request, Response, Result,
+};
+use serde_json::Value;
+
+use crate::{
+  config::Config,
+  errors::AppError,
+  policy::Policy,
+  registry::tarball_filename,
+  utils::{npm::NpmClient, url::request_origin},
+};
+
+/// `GET /:name` for npm clients. Tarball urls point back at the host the
+/// request was sent to, so installs go through the same cache and policy as
+/// the CDN routes. Versions the policy blocks are left out, along with tags
+/// pointing at them.
+pub async fn serve_packument(req: &Request, package_name: &str) -> Result<Response> {
+  let config = <&Config>::from_request_without_body(req).await?;
+  let npm = <&NpmClient>::from_request_without_body(req).await?;
+  let policy = <&Policy>::from_request_without_body(req).await?;
+  policy.check_package(package_name)?;
+
+  let mut packument = (*npm.get_packument(package_name).await?).clone();
+  packument
+    .versions
+    .retain(|version, package_config| policy.check(package_name, version, package_config).is_ok());
+  let versions = &packument.versions;
+  packument
+    .dist_tags
+    .retain(|_, version| versions.contains_key(version));
+  let origin = request_origin(req).unwrap_or_else(|| config.origin.clone());
+  let mut packument = serde_json::to_value(&packument).map_err(anyhow::Error::from)?;
+  if let Some(versions) = packument.get_mut("versions").and_then(Value::as_object_mut) {
+    for (version, package_config) in versions.iter_mut() {
+      if let Some(dist) = package_config
+        .get_mut("dist")
+        .and_then(Value::as_object_mut)
+      {
+        let tarball = format!(
+          "{origin}/{package_name}/-/{}",
+          tarball_filename(package_name, version)
+        );
+        dist.insert("tarball".into(), tarball.into());
+      }
+    }
+  }
+
+  Ok(
+    Json(packument)
+      .with_header(header::CACHE_CONTROL, "public, max-age=300")
+      .with_header(header::VARY, "X-Forwarded-Proto")
+      .with_header("Cache-Tag", "registry, packument")
+      .into_response(),
+  )
+}
+
+/// `GET /:name/-/:filename`, where the filename is `<name>-<version>.tgz`
+/// without the scope.
+pub async fn serve_tarball(req: &Request, package_name: &str, filename: &str) -> Result<Response> {
+  let npm = <&NpmClient>::from_request_without_body(req).await?;
+  let policy = <&Policy>::from_request_without_body(req).await?;
+  policy.check_package(package_name)?;
+
+  let basename = package_name.rsplit('/').next().unwrap_or(package_name);
+  let Some(version) = filename
+    .strip_suffix(".tgz")
+    .and_then(|name| name.strip_prefix(basename))
+    .and_then(|name| name.strip_prefix('-'))
+  else {
+    return Err(AppError::NotFoundPackage(format!("{package_name}/-/{filename}")).into());
+  };
+
+  let package_spec = format!("{package_name}@{version}");
+  let Some(package_config) = npm.get_package_config(package_name, version).await else {
+    return Err(AppError::NotFoundPackage(package_spec).into());
+  };
+  policy.check(package_name, version, &package_config)?;
+  let tarball = npm
+    .get_package(package_name, version, package_config.integrity())
+    .await?;
+
+  Ok(
+    StatusCode::OK
+      .with_header(header::CONTENT_TYPE, "application/octet-stream")
+      .with_header(header::CONTENT_LENGTH, tarball.len())
+      .with_header(header::CACHE_CONTROL, "public, max-age=31536000, immutable")
+      .with_header("Cache-Tag", "registry, tarball")
+      .with_body(tarball)
+      .into_response(),
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use poem::http::{header, StatusCode};
+
+  use crate::test_utils::pkg_client;
+
+  #[tokio::test]
+  async fn test_serve_registry() -> anyhow::Result<()> {
+    let cli = pkg_client().await?;
+
+    let tarball_url = |host: &str, proto: Option<&str>| {
+      let mut req = cli
+        .get("/pkg")
+        .header(header::ACCEPT, "application/vnd.npm.install-v1+json")
+        .header(header::HOST, host);
+      if let Some(proto) = proto {
+        req = req.header("X-Forwarded-Proto", proto);
+      }
+      async move {
+        let resp = req.send().await;
+        resp.assert_status_is_ok();
+        let json = resp.json().await;
+        let versions = json.value().object().get("versions").object();
+        let dist = versions.get("1.0.0").object().get("dist").object();
+        dist.get("tarball").string().to_owned()
+      }
+    };
+    assert_eq!(
+      tarball_url("registry.example.com:8080", None).await,
+      "http://registry.example.com:8080/pkg/-/pkg-1.0.0.tgz"
+    );
+    assert_eq!(
+      tarball_url("registry.example.com", Some("https")).await,
+      "https://registry.example.com/pkg/-/pkg-1.0.0.tgz"
+    );
+    assert_eq!(
+      tarball_url("registry.example.com", Some("javascript")).await,
+      "http://registry.example.com/pkg/-/pkg-1.0.0.tgz"
+    );
+
+    let resp = cli.get("/pkg/-/pkg-1.0.0.tgz").send().await;
+    resp.assert_status_is_ok();
+    resp.assert_content_type("application/octet-stream");
+
+    let resp = cli.get("/pkg/-/pkg-9.9.9.tgz").send().await;
+    resp.assert_status(StatusCode::NOT_FOUND);
+    let resp = cli
+      .get("/missing")
+      .header(header::ACCEPT, "application/json")
+      .send()
+      .await;
+    resp.assert_status(StatusCode::NOT_FOUND);
+    Ok(())
+  }
+}
@@ -1,27 +1,38 @@
 #[macro_use]
 pub(crate) mod macros;
 
+mod cache;
+pub mod config;
 mod errors;
 mod handlers;
 mod middlewares;
 mod models;
+mod policy;
+pub mod prefetch;
+pub mod registry;
+#[cfg(test)]
+mod test_utils;
 mod utils;
 
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
+use config::Config;
 use middlewares::{
-  FindEntry, ValidateFilename, ValidatePackageName, ValidatePackagePathname, ValidatePackageVersion,
+  ConditionalGet, EnforcePolicy, FindEntry, NpmRegistry, ValidateFilename, ValidatePackageName,
+  ValidatePackagePathname, ValidatePackageVersion,
 };
 use poem::{
-  endpoint::{BoxEndpoint, StaticFileEndpoint},
-  get,
-  http::header,
+  endpoint::{make_sync, BoxEndpoint, StaticFilesEndpoint},
+  http::{header, StatusCode},
   listener::TcpListener,
   middleware::{Compression, Cors, SetHeader, Tracing},
   web::CompressionLevel,
-  EndpointExt, Route,
+  Endpoint, EndpointExt, IntoResponse, Request, Response, Route, RouteMethod,
 };
+use policy::Policy;
+use registry::Registry;
 use tokio::signal;
+use utils::{npm::NpmClient, url::MountPrefix};
 
 #[poem::async_trait]
 pub trait ListenPort {
@@ -33,37 +44,105 @@ pub struct Server {
 }
 
 impl Server {
-  pub fn new() -> Self {
-    Self::default()
+  pub fn new(config: Config) -> anyhow::Result<Self> {
+    let registry = registry::from_config(&config)?;
+    Self::with_registry(config, registry)
   }
-}
 
-impl Default for Server {
-  fn default() -> Self {
-    let ep = get(handlers::handle_pkg_pathname)
-      .with(FindEntry)
-      .with(ValidateFilename)
+  pub fn with_registry(config: Config, registry: Arc<dyn Registry>) -> anyhow::Result<Self> {
+    let npm = NpmClient::new(registry, &config)?;
+    let policy = Policy::new(&config.policy)?;
+
+    let pkg = || {
+      handlers::handle_pkg_pathname
+        .with(ConditionalGet)
+        .with(FindEntry)
+        .with(ValidateFilename)
+        .with(EnforcePolicy)
+        .with(ValidatePackageVersion)
+        .with(ValidatePackageName)
+        .with(ValidatePackagePathname)
+    };
+    // HEAD keeps every header of GET and drops the body. Raw files answer it
+    // from the package index without reading their content.
+    let head = pkg().map(|mut resp: Response| async move {
+      resp.set_body(());
+      resp
+    });
+    let mut ep = RouteMethod::new().get(pkg()).head(head);
+    let mut allow = "GET, HEAD, OPTIONS";
+    if config.publish.dir.is_some() {
+      ep = ep.put(handlers::publish_package);
+      allow = "GET, HEAD, OPTIONS, PUT";
+    }
+    let ep = ep.options(make_sync(move |_| {
+      StatusCode::NO_CONTENT.with_header(header::ALLOW, allow)
+    }));
+
+    // A catch-all like `/*pkg` also matches `/` and wins over a static `/` route.
+    let homepage = Arc::new(RouteMethod::new().get(handlers::homepage));
+    let ep = ep.with(NpmRegistry).around(move |ep, req| {
+      let homepage = homepage.clone();
+      async move {
+        match req.uri().path() {
+          "/" => homepage.call(req).await,
+          _ => ep.call(req).await,
+        }
+      }
+    });
+
+    let browse = handlers::browse_package
+      .with(EnforcePolicy)
       .with(ValidatePackageVersion)
       .with(ValidatePackageName)
       .with(ValidatePackagePathname);
 
-    let ep = Route::new()
-      .at("/*pkg", ep)
-      .at(
-        "/favicon.ico",
-        StaticFileEndpoint::new("./assets/favicon.ico")
-          .with(SetHeader::new().overriding(header::CACHE_CONTROL, "public, max-age=31536000")),
-      )
-      .at(
-        "/robots.txt",
-        StaticFileEndpoint::new("./assets/robots.txt")
-          .with(SetHeader::new().overriding(header::CACHE_CONTROL, "public, max-age=31536000")),
+    let mut route = Route::new()
+      .nest(
+        "/browse",
+        RouteMethod::new().get(browse).data(MountPrefix("/browse")),
       )
+      .at("/*pkg", ep);
+    for (name, _) in handlers::EMBEDDED_ASSETS {
+      let asset = handlers::serve_asset
+        .with(ConditionalGet)
+        .with(SetHeader::new().overriding(header::CACHE_CONTROL, &config.assets.cache_control));
+      route = route.at(format!("/{name}"), RouteMethod::new().get(asset));
+    }
+    for assets in &config.assets.routes {
+      let cache_control = assets
+        .cache_control
+        .as_deref()
+        .unwrap_or("public, max-age=600");
+      route = route.nest(
+        assets.path.trim_end_matches('/'),
+        StaticFilesEndpoint::new(&assets.dir)
+          .with(SetHeader::new().overriding(header::CACHE_CONTROL, cache_control)),
+      );
+    }
+
+    let ep = route
       .with(Tracing)
       .with(Compression::new().with_quality(CompressionLevel::Fastest))
-      .with(Cors::new());
+      // Byte ranges count uncompressed bytes, so they are never compressed.
+      .before(|mut req: Request| async move {
+        if req.headers().contains_key(header::RANGE) {
+          req.headers_mut().remove(header::ACCEPT_ENCODING);
+        }
+        Ok(req)
+      })
+      .with(Cors::new())
+      .data(Arc::new(config))
+      .data(Arc::new(npm))
+      .data(Arc::new(policy));
+
+    Ok(Self { ep: ep.boxed() })
+  }
+}
 
-    Self { ep: ep.boxed() }
+impl Default for Server {
+  fn default() -> Self {
+    Self::new(Config::default()).expect("create server with the default config")
   }
 }
 
@@ -104,3 +183,37 @@ async fn shutdown_signal() {
 
   tracing::info!("signal received, starting graceful shutdown");
 }
+
+#[cfg(test)]
+mod tests {
+  use poem::http::{header, StatusCode};
+
+  use crate::test_utils::pkg_client;
+
+  #[tokio::test]
+  async fn test_head_and_options() -> anyhow::Result<()> {
+    let cli = pkg_client().await?;
+
+    let get = cli.get("/pkg@1.0.0/index.js").send().await;
+    let resp = cli.head("/pkg@1.0.0/index.js").send().await;
+    resp.assert_status_is_ok();
+    resp.assert_header(header::CONTENT_LENGTH, "17");
+    resp.assert_header(header::ETAG, get.0.header(header::ETAG).unwrap());
+    resp.assert_text("").await;
+
+    let resp = cli.head("/pkg").send().await;
+    resp.assert_status(StatusCode::FOUND);
+    resp.assert_header(header::LOCATION, "/pkg@1.0.0");
+    let resp = cli.head("/pkg@1.0.0/?meta").send().await;
+    resp.assert_status_is_ok();
+    resp.assert_content_type("application/json; charset=utf-8");
+    let resp = cli.head("/pkg/-/pkg-1.0.0.tgz").send().await;
+    resp.assert_status_is_ok();
+    resp.assert_text("").await;
+
+    let resp = cli.options("/pkg@1.0.0/index.js").send().await;
+    resp.assert_status(StatusCode::NO_CONTENT);
+    resp.assert_header(header::ALLOW, "GET, HEAD, OPTIONS");
+    Ok(())
+  }
+}
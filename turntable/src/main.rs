@@ -1,16 +1,13 @@
+use anyhow::Context;
+use clap::Parser;
 use tracing_subscriber::prelude::*;
-use turntable::ListenPort;
-
-#[inline]
-fn get_port() -> u16 {
-  option_env!("PORT")
-    .get_or_insert("8080")
-    .parse::<u16>()
-    .expect("get port ok")
-}
+use turntable::{
+  config::{Cli, Command, Config, PrefetchArgs},
+  prefetch, registry, ListenPort,
+};
 
 #[tokio::main]
-async fn main() {
+async fn main() -> anyhow::Result<()> {
   tracing_subscriber::registry()
     .with(tracing_subscriber::fmt::layer().with_target(false))
     .with(
@@ -19,6 +16,41 @@ async fn main() {
     )
     .init();
 
-  let port: u16 = get_port();
-  turntable::Server::new().listen(port).await.unwrap();
+  let cli = Cli::parse();
+  let config = Config::load(&cli)?;
+  tracing::debug!("{config:?}");
+
+  match cli.command {
+    Some(Command::Prefetch(ref args)) => run_prefetch(config, args).await,
+    Some(Command::Serve) | None => {
+      let port = config.port;
+      turntable::Server::new(config)?.listen(port).await
+    }
+  }
+}
+
+async fn run_prefetch(config: Config, args: &PrefetchArgs) -> anyhow::Result<()> {
+  let mut specs = vec![];
+  for file in args.files.iter() {
+    let content =
+      std::fs::read_to_string(file).with_context(|| format!("read {}", file.display()))?;
+    specs.extend(
+      prefetch::parse(file, &content).with_context(|| format!("parse {}", file.display()))?,
+    );
+  }
+  specs.sort();
+  specs.dedup();
+
+  let registry = registry::from_config(&config)?;
+  let report = prefetch::prefetch(&config, registry, specs, args.concurrency).await?;
+
+  println!("Prefetched {} packages", report.fetched.len());
+  if report.failures.is_empty() {
+    return Ok(());
+  }
+  eprintln!("Failed to prefetch {} packages:", report.failures.len());
+  for failure in report.failures.iter() {
+    eprintln!("  {}: {}", failure.spec, failure.error);
+  }
+  std::process::exit(1);
 }
@@ -0,0 +1,189 @@
+use poem::{
+  http::{header, HeaderMap, Method, StatusCode},
+  web::headers::{ETag, HeaderMapExt, IfModifiedSince, IfNoneMatch, LastModified},
+  Endpoint, FromRequest, IntoResponse, Middleware, Request, Response, Result,
+};
+
+use crate::{
+  models::{Entry, PackageQuery},
+  utils::headers::file_headers,
+};
+
+/// `If-None-Match` wins over `If-Modified-Since`, and both over `Range`, as
+/// RFC 9110 asks. ETags are compared weakly, so `W/"x"` matches `"x"`.
+fn is_not_modified(method: &Method, headers: &HeaderMap, resp: &Response) -> bool {
+  if !matches!(*method, Method::GET | Method::HEAD)
+    || !matches!(resp.status(), StatusCode::OK | StatusCode::PARTIAL_CONTENT)
+  {
+    return false;
+  }
+  if let Some(if_none_match) = headers.typed_get::<IfNoneMatch>() {
+    return resp
+      .headers()
+      .typed_get::<ETag>()
+      .is_some_and(|etag| !if_none_match.precondition_passes(&etag));
+  }
+  match (
+    headers.typed_get::<IfModifiedSince>(),
+    resp.headers().typed_get::<LastModified>(),
+  ) {
+    (Some(since), Some(last_modified)) => !since.is_modified(last_modified.into()),
+    _ => false,
+  }
+}
+
+#[inline]
+fn not_modified(mut resp: Response) -> Response {
+  resp.set_status(StatusCode::NOT_MODIFIED);
+  resp.set_body(());
+  resp.headers_mut().remove(header::CONTENT_TYPE);
+  resp.headers_mut().remove(header::CONTENT_LENGTH);
+  resp.headers_mut().remove(header::CONTENT_RANGE);
+  resp
+}
+
+/// Answers `304 Not Modified` without a body when the client already has the
+/// response, keeping its validators and cache headers.
+///
+/// Raw files are checked against the validators of their index entry before
+/// the endpoint runs, other responses once the endpoint has built them.
+pub struct ConditionalGet;
+
+impl<E: Endpoint> Middleware<E> for ConditionalGet {
+  type Output = ConditionalGetEndpoint<E>;
+
+  fn transform(&self, ep: E) -> Self::Output {
+    ConditionalGetEndpoint { ep }
+  }
+}
+
+pub struct ConditionalGetEndpoint<E> {
+  ep: E,
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for ConditionalGetEndpoint<E> {
+  type Output = Response;
+
+  async fn call(&self, req: Request) -> Result<Self::Output> {
+    if let Some(entry) = req.extensions().get::<Entry>() {
+      let raw = PackageQuery::from_request_without_body(&req)
+        .await
+        .is_ok_and(|query| query.is_raw());
+      let resp = file_headers(entry);
+      if raw && is_not_modified(req.method(), req.headers(), &resp) {
+        return Ok(not_modified(resp));
+      }
+    }
+
+    let method = req.method().clone();
+    let headers = req.headers().clone();
+    let resp = self.ep.call(req).await?.into_response();
+    match is_not_modified(&method, &headers, &resp) {
+      true => Ok(not_modified(resp)),
+      false => Ok(resp),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+  };
+
+  use poem::{
+    endpoint::make_sync,
+    http::{header, StatusCode},
+    EndpointExt,
+  };
+
+  use super::*;
+  use crate::test_utils::pkg_client;
+
+  #[tokio::test]
+  async fn test_file_not_modified_skips_endpoint() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let ep = {
+      let calls = calls.clone();
+      make_sync(move |_| {
+        calls.fetch_add(1, Ordering::SeqCst);
+        "export default 1;"
+      })
+      .with(ConditionalGet)
+    };
+    let request = |uri: &str| {
+      let mut req = Request::builder()
+        .uri(uri.parse().unwrap())
+        .header(header::IF_NONE_MATCH, "W/\"11-a\"")
+        .finish();
+      req.extensions_mut().insert(Entry {
+        path: "/index.js".into(),
+        etag: "W/\"11-a\"".into(),
+        ..Default::default()
+      });
+      req
+    };
+
+    let resp = ep.get_response(request("/pkg@1.0.0/index.js")).await;
+    assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(resp.headers()[header::ETAG], "W/\"11-a\"");
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+    // Modules are rewritten, so the file's validators do not apply to them.
+    let resp = ep.get_response(request("/pkg@1.0.0/index.js?module")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+  }
+
+  #[tokio::test]
+  async fn test_conditional_requests() -> anyhow::Result<()> {
+    let cli = pkg_client().await?;
+
+    for path in ["/pkg@1.0.0/index.js", "/pkg@1.0.0/index.js?meta"] {
+      let resp = cli.get(path).send().await;
+      resp.assert_status_is_ok();
+      let etag = resp.0.header(header::ETAG).unwrap().to_owned();
+
+      let resp = cli
+        .get(path)
+        .header(header::IF_NONE_MATCH, &etag)
+        .send()
+        .await;
+      resp.assert_status(StatusCode::NOT_MODIFIED);
+      resp.assert_header(header::ETAG, &etag);
+      resp.assert_text("").await;
+
+      let strong = etag.trim_start_matches("W/");
+      let resp = cli
+        .get(path)
+        .header(header::IF_NONE_MATCH, strong)
+        .send()
+        .await;
+      resp.assert_status(StatusCode::NOT_MODIFIED);
+      let resp = cli
+        .get(path)
+        .header(header::IF_NONE_MATCH, "\"other\"")
+        .send()
+        .await;
+      resp.assert_status_is_ok();
+    }
+
+    let resp = cli.get("/pkg@1.0.0/index.js").send().await;
+    let last_modified = resp.0.header(header::LAST_MODIFIED).unwrap().to_owned();
+    let resp = cli
+      .get("/pkg@1.0.0/index.js")
+      .header(header::IF_MODIFIED_SINCE, &last_modified)
+      .send()
+      .await;
+    resp.assert_status(StatusCode::NOT_MODIFIED);
+    let resp = cli
+      .get("/pkg@1.0.0/index.js")
+      .header(header::IF_MODIFIED_SINCE, "Thu, 01 Jan 1970 00:00:00 GMT")
+      .send()
+      .await;
+    resp.assert_status_is_ok();
+    Ok(())
+  }
+}
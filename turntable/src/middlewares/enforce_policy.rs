@@ -0,0 +1,108 @@
+use poem::{Endpoint, FromRequest, Middleware, Request, Result};
+
+use crate::{
+  models::{PackageConfig, PackagePathname},
+  policy::Policy,
+};
+
+/// Rejects a resolved version that the policy blocks, including by license.
+/// Resolution already skips blocked versions for ranges, so this catches
+/// exact versions and tags.
+pub struct EnforcePolicy;
+
+impl<E: Endpoint> Middleware<E> for EnforcePolicy {
+  type Output = EnforcePolicyEndpoint<E>;
+
+  fn transform(&self, ep: E) -> Self::Output {
+    EnforcePolicyEndpoint { ep }
+  }
+}
+
+pub struct EnforcePolicyEndpoint<E> {
+  ep: E,
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for EnforcePolicyEndpoint<E> {
+  type Output = E::Output;
+
+  async fn call(&self, req: Request) -> Result<Self::Output> {
+    let policy = <&Policy>::from_request_without_body(&req).await?;
+    let pkg = <&PackagePathname>::from_request_without_body(&req).await?;
+    let package_config = <&PackageConfig>::from_request_without_body(&req).await?;
+
+    policy.check(&pkg.package_name, &pkg.package_version, package_config)?;
+
+    self.ep.call(req).await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use poem::http::{header, StatusCode};
+
+  use crate::{
+    config::{Config, PolicyConfig, PolicyRule},
+    registry::MemoryRegistry,
+    test_utils::{insert_package, test_client_with},
+  };
+
+  #[tokio::test]
+  async fn test_policy() -> anyhow::Result<()> {
+    let registry = MemoryRegistry::new();
+    for (version, license) in [
+      ("1.0.0", "MIT"),
+      ("1.1.0", "MIT"),
+      ("1.2.0", "GPL-3.0-only"),
+    ] {
+      insert_package(
+        &registry,
+        serde_json::json!({ "name": "pkg", "version": version, "license": license }),
+        &[("/index.js", "export default 1;")],
+      )
+      .await?;
+    }
+    let config = Config {
+      policy: PolicyConfig {
+        deny: vec![PolicyRule {
+          package: "pkg".into(),
+          versions: Some("1.1.0".into()),
+          reason: Some("compromised".into()),
+        }],
+        licenses: vec!["MIT".into()],
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    let cli = test_client_with(config, registry)?;
+
+    let resp = cli.get("/pkg@~1.1.0/index.js").send().await;
+    resp.assert_status(StatusCode::FORBIDDEN);
+    resp.assert_text("pkg@1.1.0 is blocked (compromised)").await;
+
+    let resp = cli.get("/pkg@^1.0.0/index.js").send().await;
+    resp.assert_header(header::LOCATION, "/pkg@1.0.0/index.js");
+    let resp = cli.get("/pkg@~1.2.0/index.js").send().await;
+    resp.assert_status(StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS);
+    let resp = cli.get("/pkg@1.2.0/index.js").send().await;
+    resp.assert_status(StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS);
+    cli
+      .get("/pkg@1.0.0/index.js")
+      .send()
+      .await
+      .assert_status_is_ok();
+
+    let resp = cli
+      .get("/pkg")
+      .header(header::ACCEPT, "application/json")
+      .send()
+      .await;
+    let json = resp.json().await;
+    let versions = json.value().object().get("versions").object();
+    versions.assert_len(1);
+    versions.get("1.0.0").object();
+    let resp = cli.get("/pkg/-/pkg-1.1.0.tgz").send().await;
+    resp.assert_status(StatusCode::FORBIDDEN);
+    Ok(())
+  }
+}
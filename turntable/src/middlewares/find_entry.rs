@@ -1,25 +1,15 @@
-use std::{collections::HashMap, ops::Deref, path::PathBuf};
+use std::path::PathBuf;
 
 use crate::{
   errors::AppError,
-  models::{Entry, PackagePathname, PackageQuery},
-  utils::{
-    encrypt::get_intergrity,
-    fs::get_content_type,
-    npm::{get_package, SearchEntry},
-    redirect,
-    url::create_pkg_url,
-  },
+  models::{Entry, PackageConfig, PackageIndex, PackagePathname, PackageQuery},
+  utils::{npm::NpmClient, redirect, url::create_pkg_url},
 };
-use async_compression::tokio::bufread::GzipDecoder;
-use bytes::Bytes;
-use chrono::NaiveDateTime;
 use poem::{
-  http::header, Endpoint, FromRequest, IntoResponse, Middleware, Request, Response, Result,
+  http::{header, Method},
+  Endpoint, FromRequest, IntoResponse, Middleware, Request, Response, Result,
 };
-use tokio::io::AsyncReadExt;
-use tokio_stream::StreamExt;
-use tokio_tar::{Archive, EntryType};
+use tokio_tar::EntryType;
 
 #[inline]
 fn file_redirect(pkg: &PackagePathname, entry: &Entry, raw_query: Option<&str>) -> Response {
@@ -47,113 +37,28 @@ fn index_redirect(pkg: &PackagePathname, entry: &Entry, raw_query: Option<&str>)
   .into_response()
 }
 
-pub async fn search_entries(
-  steam: Bytes,
-  filename: impl AsRef<str>,
-) -> anyhow::Result<SearchEntry> {
-  let filename = filename.as_ref();
-  let tar = GzipDecoder::new(steam.deref());
-  let mut ar = Archive::new(tar);
-
-  let js_entry_filename = format!("{filename}.js");
-  let json_entry_filename = format!("{filename}.json");
-
-  let mut matching_entries = HashMap::new();
-  let mut found_entry: Option<Entry> = None;
-
-  if filename == "/" {
-    let entry = Entry {
+/// Finds `filename` itself, then `filename.js` and `filename.json`, and finally
+/// a directory called `filename`.
+fn search_entry(index: &PackageIndex, filename: &str, with_content: bool) -> Option<Entry> {
+  [
+    filename.to_owned(),
+    format!("{filename}.js"),
+    format!("{filename}.json"),
+  ]
+  .iter()
+  .find_map(|path| match with_content {
+    true => index.entry(path),
+    false => index.entry_without_content(path),
+  })
+  .or_else(|| {
+    index.is_dir(filename).then(|| Entry {
       path: PathBuf::from(filename),
       entry_type: EntryType::Directory,
       ..Default::default()
-    };
-    found_entry = Some(entry.clone());
-    matching_entries.insert(filename.into(), entry);
-  }
-
-  let mut entries = ar.entries()?;
-  while let Some(Ok(mut file)) = entries.next().await {
-    // Make sure there wasn't an I/O error
-    let mut path = file.path()?.to_path_buf();
-
-    if !path.starts_with("/") {
-      path = PathBuf::from("/").join(path.iter().skip(1).collect::<PathBuf>());
-    }
-
-    let mut entry = Entry {
-      content_type: get_content_type(&path),
-      path,
-      entry_type: file.header().entry_type(),
-      last_modified: NaiveDateTime::from_timestamp_opt(file.header().mtime()?.try_into()?, 0)
-        .expect("get last_modified")
-        .format("%a, %d %b %Y %H:%M:%S GMT")
-        .to_string(),
-      size: file.header().size()?,
-      ..Default::default()
-    };
-
-    if !entry.entry_type.is_file() || !entry.path.starts_with(filename) {
-      continue;
-    }
-
-    for p in entry.path.ancestors() {
-      let dir = p.parent().and_then(|d| d.to_str());
-      if dir.is_none() || dir.is_some_and(|f| f == "/") {
-        break;
-      }
-
-      let dir = dir.unwrap();
-
-      if !matching_entries.contains_key(dir) {
-        matching_entries.insert(
-          dir.into(),
-          Entry {
-            path: PathBuf::from(dir),
-            entry_type: EntryType::Directory,
-            ..Default::default()
-          },
-        );
-      }
-    }
-
-    let path = entry.path.to_str().expect("get entry path");
-    if path == filename || path == js_entry_filename || path == json_entry_filename {
-      if let Some(ref f_entry) = found_entry {
-        let f_path = f_entry.path.to_str().expect("get found_entry path");
-        if f_path != filename
-          && (path == filename || (path == js_entry_filename && f_path == json_entry_filename))
-        {
-          read_entry_file(&mut file, &mut entry).await?;
-
-          found_entry = Some(entry.clone());
-        }
-      } else {
-        read_entry_file(&mut file, &mut entry).await?;
-
-        found_entry = Some(entry.clone());
-      }
-    }
-
-    matching_entries.insert(entry.path.display().to_string(), entry);
-  }
-
-  Ok(SearchEntry {
-    found_entry,
-    matching_entries,
+    })
   })
 }
 
-async fn read_entry_file(
-  file: &mut tokio_tar::Entry<Archive<GzipDecoder<&[u8]>>>,
-  entry: &mut Entry,
-) -> anyhow::Result<()> {
-  let mut content = Vec::new();
-  file.read_to_end(&mut content).await?;
-  entry.integrity = get_intergrity(&content)?;
-  entry.content = content.into();
-  Ok(())
-}
-
 pub struct FindEntry;
 
 impl<E: Endpoint> Middleware<E> for FindEntry {
@@ -173,35 +78,37 @@ impl<E: Endpoint> Endpoint for FindEntryEndpoint<E> {
   type Output = Response;
 
   async fn call(&self, mut req: Request) -> Result<Self::Output> {
-    if PackageQuery::from_request_without_body(&req)
-      .await?
-      .meta
-      .is_some()
-    {
+    let query = PackageQuery::from_request_without_body(&req).await?;
+    if query.meta.is_some() {
       return Ok(self.ep.call(req).await?.into_response());
     }
+    // `HEAD` of a raw file is answered from the index alone.
+    let with_content = req.method() != Method::HEAD || !query.is_raw();
 
+    let npm = <&NpmClient>::from_request_without_body(&req).await?;
     let pkg = <&PackagePathname>::from_request_without_body(&req).await?;
+    let package_config = <&PackageConfig>::from_request_without_body(&req).await?;
 
-    let stream = get_package(&pkg.package_name, &pkg.package_version).await?;
-    let SearchEntry {
-      found_entry: entry,
-      matching_entries,
-    } = search_entries(stream, &pkg.filename).await?;
+    let index = npm
+      .get_package_index(
+        &pkg.package_name,
+        &pkg.package_version,
+        package_config.integrity(),
+      )
+      .await?;
 
-    let entry = match entry {
+    let entry = match search_entry(&index, &pkg.filename, with_content) {
       Some(entry) if entry.entry_type.is_file() && entry.path.to_string_lossy() != pkg.filename => {
         return Ok(file_redirect(pkg, &entry, req.uri().query()));
       }
       Some(entry) if entry.entry_type.is_dir() => {
-        let index_entry = matching_entries
-          .get(&format!("{}/index.js", pkg.filename))
-          .or(matching_entries.get(&format!("{}/index.json", pkg.filename)));
+        let dir = pkg.filename.trim_end_matches('/');
+        let index_entry = index
+          .entry(&format!("{dir}/index.js"))
+          .or_else(|| index.entry(&format!("{dir}/index.json")));
 
         return match index_entry {
-          Some(entry) if entry.entry_type.is_file() => {
-            Ok(index_redirect(pkg, entry, req.uri().query()))
-          }
+          Some(entry) => Ok(index_redirect(pkg, &entry, req.uri().query())),
           _ => Err(AppError::NotFoundIndexFileInPackage {
             filename: pkg.filename.clone(),
             package_spec: pkg.package_spec.clone(),
@@ -1,10 +1,16 @@
+mod conditional_get;
+mod enforce_policy;
 mod find_entry;
+mod npm_registry;
 mod validate_filename;
 mod validate_package_name;
 mod validate_package_pathname;
 mod validate_package_version;
 
+pub use conditional_get::*;
+pub use enforce_policy::*;
 pub use find_entry::*;
+pub use npm_registry::*;
 pub use validate_filename::*;
 pub use validate_package_name::*;
 pub use validate_package_pathname::*;
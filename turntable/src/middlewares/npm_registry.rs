@@ -0,0 +1,122 @@
+use poem::{
+  http::{header, Method},
+  Endpoint, IntoResponse, Middleware, Request, Response, Result,
+};
+
+use crate::handlers::{serve_packument, serve_tarball};
+
+/// What an npm client asks a registry for.
+#[derive(Debug, PartialEq)]
+enum RegistryRequest {
+  Packument(String),
+  Tarball {
+    package_name: String,
+    filename: String,
+  },
+}
+
+#[inline]
+fn accepts_packument(req: &Request) -> bool {
+  req
+    .headers()
+    .get(header::ACCEPT)
+    .and_then(|value| value.to_str().ok())
+    .is_some_and(|accept| {
+      accept.contains("application/vnd.npm.install-v1+json")
+        || accept.starts_with("application/json")
+    })
+}
+
+/// Tarball paths never clash with CDN paths, which always carry a version
+/// after resolving. A bare package path is only a packument request when the
+/// client asks for JSON, or when the scope is encoded like `@scope%2fname` as
+/// npm does.
+fn parse_registry_request(req: &Request) -> Option<RegistryRequest> {
+  if !matches!(*req.method(), Method::GET | Method::HEAD) {
+    return None;
+  }
+  let raw_path = req.uri().path();
+  let path = urlencoding::decode(raw_path).ok()?;
+
+  if let Some(caps) = regex!(r"^/((?:@[^/@]+/)?[^/@]+)/-/([^/]+\.tgz)$").captures(&path) {
+    return Some(RegistryRequest::Tarball {
+      package_name: caps[1].to_owned(),
+      filename: caps[2].to_owned(),
+    });
+  }
+
+  let caps = regex!(r"^/((?:@[^/@]+/)?[^/@]+)$").captures(&path)?;
+  let encoded_scope = raw_path.contains("%2f") || raw_path.contains("%2F");
+  (encoded_scope || accepts_packument(req)).then(|| RegistryRequest::Packument(caps[1].to_owned()))
+}
+
+/// Answers npm registry requests and passes everything else on.
+pub struct NpmRegistry;
+
+impl<E: Endpoint> Middleware<E> for NpmRegistry {
+  type Output = NpmRegistryEndpoint<E>;
+
+  fn transform(&self, ep: E) -> Self::Output {
+    NpmRegistryEndpoint { ep }
+  }
+}
+
+pub struct NpmRegistryEndpoint<E> {
+  ep: E,
+}
+
+#[poem::async_trait]
+impl<E: Endpoint> Endpoint for NpmRegistryEndpoint<E> {
+  type Output = Response;
+
+  async fn call(&self, req: Request) -> Result<Self::Output> {
+    let mut resp = match parse_registry_request(&req) {
+      Some(RegistryRequest::Packument(package_name)) => {
+        serve_packument(&req, &package_name).await?
+      }
+      Some(RegistryRequest::Tarball {
+        package_name,
+        filename,
+      }) => serve_tarball(&req, &package_name, &filename).await?,
+      None => return Ok(self.ep.call(req).await?.into_response()),
+    };
+    if req.method() == Method::HEAD {
+      resp.set_body(());
+    }
+    Ok(resp)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(path: &str, accept: &str) -> Option<RegistryRequest> {
+    let req = Request::builder()
+      .uri(path.parse().unwrap())
+      .header(header::ACCEPT, accept)
+      .finish();
+    parse_registry_request(&req)
+  }
+
+  #[test]
+  fn test_parse_registry_request() {
+    let npm_accept = "application/vnd.npm.install-v1+json; q=1.0, application/json; q=0.8, */*";
+    let packument = |name: &str| Some(RegistryRequest::Packument(name.into()));
+
+    assert_eq!(parse("/react", npm_accept), packument("react"));
+    assert_eq!(parse("/@scope%2fname", "*/*"), packument("@scope/name"));
+    assert_eq!(parse("/@scope/name", npm_accept), packument("@scope/name"));
+    assert_eq!(
+      parse("/@scope/name/-/name-1.0.0.tgz", "*/*"),
+      Some(RegistryRequest::Tarball {
+        package_name: "@scope/name".into(),
+        filename: "name-1.0.0.tgz".into(),
+      })
+    );
+
+    assert_eq!(parse("/react", "text/html,*/*"), None);
+    assert_eq!(parse("/react@18.2.0", npm_accept), None);
+    assert_eq!(parse("/react/index.js", npm_accept), None);
+  }
+}
@@ -50,7 +50,7 @@ async fn filename_redirect(pkg: &PackagePathname, req: &Request) -> Result<Respo
         .or(package_config.get_str("jsnext:main"))
         .unwrap_or_default()
     })
-    .filter(|filename| filename.is_empty())
+    .filter(|filename| !filename.is_empty())
     .or_else(|| {
       let ty = package_config.get_str("type");
       match package_config.get_str("main") {
@@ -77,6 +77,7 @@ async fn filename_redirect(pkg: &PackagePathname, req: &Request) -> Result<Respo
       .main
       .as_ref()
       .and_then(|main| package_config.get_str(main))
+      .or_else(|| query.css.as_ref().and(package_config.get_str("style")))
       .or_else(|| package_config.get_str("unpkg"))
       .or_else(|| package_config.get_str("browser"))
       .or_else(|| package_config.get_str("main").or(Some("/index.js")))
@@ -95,3 +96,42 @@ async fn filename_redirect(pkg: &PackagePathname, req: &Request) -> Result<Respo
     .into_response();
   Ok(resp)
 }
+
+#[cfg(test)]
+mod tests {
+  use poem::http::header;
+
+  use crate::test_utils::test_client;
+
+  #[tokio::test]
+  async fn test_filename_redirect() -> anyhow::Result<()> {
+    let cli = test_client(
+      serde_json::json!({
+        "name": "pkg",
+        "version": "1.0.0",
+        "main": "lib/index.js",
+        "module": "esm/index.js"
+      }),
+      &[
+        ("/lib/index.js", "exports.a = 1;"),
+        ("/esm/index.js", "export const a = 1;"),
+      ],
+    )
+    .await?;
+
+    let resp = cli.get("/pkg@1.0.0").send().await;
+    resp.assert_header(header::LOCATION, "/pkg@1.0.0/lib/index.js");
+    let resp = cli.get("/pkg@1.0.0?module").send().await;
+    resp.assert_header(header::LOCATION, "/pkg@1.0.0/esm/index.js?module");
+
+    // An empty `module` field is skipped like a missing one.
+    let cli = test_client(
+      serde_json::json!({ "name": "pkg", "version": "1.0.0", "main": "lib/index.js", "module": "" }),
+      &[("/lib/index.js", "exports.a = 1;")],
+    )
+    .await?;
+    let resp = cli.get("/pkg@1.0.0?module").send().await;
+    resp.assert_header(header::LOCATION, "/pkg@1.0.0/lib/index.js?module");
+    Ok(())
+  }
+}
@@ -1,15 +1,18 @@
 use node_semver::{Range, Version};
 use poem::{
-  http::header, Endpoint, FromRequest, IntoResponse, Middleware, Request, Response, Result,
+  http::{header, HeaderValue},
+  Endpoint, FromRequest, IntoResponse, Middleware, Request, Response, Result,
 };
 
 use crate::{
   errors::AppError,
   models::PackagePathname,
+  policy::Policy,
+  registry::VersionsAndTags,
   utils::{
-    npm::{get_package_config, get_versions_and_tags, VersionsAndTags},
+    npm::NpmClient,
     redirect,
-    url::create_pkg_url,
+    url::{create_pkg_url, mount_prefix},
   },
 };
 
@@ -28,18 +31,53 @@ fn max_satisfies(versions: Vec<String>, range: Range) -> anyhow::Result<Option<S
   Ok(max)
 }
 
-async fn resolve_version(
+/// Resolves a tag or range to a version, and tells whether the version list
+/// used for it was stale. Ranges skip versions blocked by the policy, by rule
+/// or by license, while an exact version or tag pointing at one is rejected.
+pub(crate) async fn resolve_version(
+  npm: &NpmClient,
+  policy: &Policy,
   package_name: impl AsRef<str>,
   package_version: impl Into<String>,
-) -> anyhow::Result<Option<String>> {
+) -> anyhow::Result<(Option<String>, bool)> {
+  let package_name = package_name.as_ref();
+  policy.check_package(package_name)?;
+
   let package_version = package_version.into();
-  let VersionsAndTags { versions, tags } = get_versions_and_tags(package_name).await?;
+  let (VersionsAndTags { versions, tags }, stale) = npm.get_versions_and_tags(package_name).await?;
   let package_version = tags.get(&package_version).unwrap_or(&package_version);
 
-  match versions.contains(package_version) {
-    true => Ok(Some(package_version.to_owned())),
-    false => max_satisfies(versions, Range::parse(package_version)?),
+  if versions.contains(package_version) {
+    policy.check_version(package_name, package_version)?;
+    return Ok((Some(package_version.to_owned()), stale));
+  }
+  let range = Range::parse(package_version)?;
+  let allowed = policy
+    .allowed_versions(npm, package_name, versions.clone())
+    .await?;
+  let version = max_satisfies(allowed, range.clone())?;
+  if version.is_none() {
+    // Tell why the range cannot be served rather than reporting it missing.
+    if let Some(blocked) = max_satisfies(versions, range)? {
+      policy.check_version(package_name, &blocked)?;
+      if let Some(config) = npm.get_package_config(package_name, &blocked).await {
+        policy.check(package_name, &blocked, &config)?;
+      }
+    }
+  }
+  Ok((version, stale))
+}
+
+/// Marks a response built from a stale version list.
+#[inline]
+fn mark_stale(mut resp: Response, stale: bool) -> Response {
+  if stale {
+    resp.headers_mut().insert(
+      header::WARNING,
+      HeaderValue::from_static("110 - \"Response is Stale\""),
+    );
   }
+  resp
 }
 
 pub struct ValidatePackageVersion;
@@ -61,9 +99,13 @@ impl<E: Endpoint> Endpoint for ValidatePackageVersionEndpoint<E> {
   type Output = Response;
 
   async fn call(&self, mut req: Request) -> Result<Self::Output> {
+    let npm = <&NpmClient>::from_request_without_body(&req).await?;
+    let policy = <&Policy>::from_request_without_body(&req).await?;
     let pkg = <&PackagePathname>::from_request_without_body(&req).await?;
 
-    let version = resolve_version(&pkg.package_name, &pkg.package_version).await?;
+    let (version, stale) = resolve_version(npm, policy, &pkg.package_name, &pkg.package_version)
+      .await
+      .map_err(AppError::from)?;
 
     let Some(version) = version else{
                 return Err(AppError::NotFoundPackage(pkg.package_spec.to_owned())).map_err(Into::into);
@@ -71,22 +113,30 @@ impl<E: Endpoint> Endpoint for ValidatePackageVersionEndpoint<E> {
 
     if version != pkg.package_version {
       let path = create_pkg_url(&pkg.package_name, version, &pkg.filename, req.uri().query());
+      let path = format!("{}{path}", mount_prefix(&req));
+      // Shared caches should not keep a redirect resolved from stale data.
+      let cache_control = match stale {
+        true => "public, max-age=60",
+        false => "public, s-maxage=600, max-age=60",
+      };
       let resp = redirect(path)
-        .with_header(header::CACHE_CONTROL, "public, s-maxage=600, max-age=60")
+        .with_header(header::CACHE_CONTROL, cache_control)
         .with_header("Cache-Tag", "redirect, semver-redirect")
         .into_response();
-      return Ok(resp);
+      return Ok(mark_stale(resp, stale));
     }
 
-    let Some(config) =  get_package_config(&pkg.package_name, &pkg.package_version).await else {
+    let Some(package_config) = npm
+      .get_package_config(&pkg.package_name, &pkg.package_version)
+      .await else {
                 return Err(AppError::UnableGetConfigForPackage(
                     pkg.package_spec.to_owned(),
                 ))
                 .map_err(Into::<poem::Error>::into);
             };
 
-    req.extensions_mut().insert(config);
+    req.extensions_mut().insert(package_config);
 
-    Ok(self.ep.call(req).await?.into_response())
+    Ok(mark_stale(self.ep.call(req).await?.into_response(), stale))
   }
 }
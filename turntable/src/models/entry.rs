@@ -12,6 +12,7 @@ pub struct Entry {
   pub entry_type: EntryType,
   pub content_type: Mime,
   pub integrity: String,
+  pub etag: String,
   pub last_modified: String,
   pub size: u64,
   pub content: Bytes,
@@ -24,6 +25,7 @@ impl Default for Entry {
       entry_type: EntryType::Regular,
       content_type: mime_guess::mime::TEXT_PLAIN,
       integrity: Default::default(),
+      etag: Default::default(),
       last_modified: Default::default(),
       size: Default::default(),
       content: Default::default(),
@@ -1,12 +1,14 @@
 mod entry;
 mod metadata;
 mod package_config;
+mod package_index;
 mod package_pathname;
 
 use chrono::{DateTime, NaiveDateTime, Utc};
 pub use entry::*;
 pub use metadata::*;
 pub use package_config::*;
+pub use package_index::*;
 pub use package_pathname::*;
 use poem::web::Query;
 use serde::Deserialize;
@@ -16,6 +18,16 @@ pub struct OptionInQuery {
   pub module: Option<String>,
   pub meta: Option<String>,
   pub main: Option<String>,
+  pub css: Option<String>,
+}
+
+impl OptionInQuery {
+  /// Whether the file itself is served, rather than its metadata or a
+  /// rewritten module or stylesheet.
+  #[inline]
+  pub fn is_raw(&self) -> bool {
+    self.module.is_none() && self.meta.is_none() && self.css.is_none()
+  }
 }
 
 pub type PackageQuery = Query<OptionInQuery>;
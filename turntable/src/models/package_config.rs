@@ -1,9 +1,11 @@
 use std::ops::Deref;
 
 use poem::{FromRequest, Request, RequestBody};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+use crate::utils::encrypt::shasum_to_integrity;
+
 fn merge(a: &mut Value, b: &Value) {
   match (a, b) {
     (&mut Value::Object(ref mut a), Value::Object(b)) => {
@@ -17,7 +19,7 @@ fn merge(a: &mut Value, b: &Value) {
   }
 }
 
-#[derive(Debug, Clone, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct PackageConfig(Value);
 
 impl PackageConfig {
@@ -26,6 +28,44 @@ impl PackageConfig {
     self.get(key.as_ref()).and_then(|value| value.as_str())
   }
 
+  /// `dist.integrity` of this version, falling back to `dist.shasum`.
+  pub fn integrity(&self) -> Option<String> {
+    let dist = self.get("dist")?;
+    dist
+      .get("integrity")
+      .and_then(|value| value.as_str())
+      .map(ToOwned::to_owned)
+      .or_else(|| {
+        dist
+          .get("shasum")
+          .and_then(|value| value.as_str())
+          .and_then(shasum_to_integrity)
+      })
+  }
+
+  /// The SPDX license expression. Old packages use a `{ "type": .. }` object
+  /// or a `licenses` list, which is read as a choice between its entries.
+  pub fn license(&self) -> Option<String> {
+    let license_type = |value: &Value| match value {
+      Value::String(license) => Some(license.to_owned()),
+      value => value.get("type")?.as_str().map(ToOwned::to_owned),
+    };
+    if let Some(license) = self.get("license") {
+      return license_type(license);
+    }
+    let licenses = self
+      .get("licenses")?
+      .as_array()?
+      .iter()
+      .filter_map(license_type)
+      .collect::<Vec<_>>();
+    match licenses.len() {
+      0 => None,
+      1 => licenses.into_iter().next(),
+      _ => Some(format!("({})", licenses.join(" OR "))),
+    }
+  }
+
   #[inline]
   pub fn dependencies(&self) -> Value {
     let mut dependencies = self
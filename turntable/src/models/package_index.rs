@@ -0,0 +1,257 @@
+use std::{
+  collections::BTreeMap,
+  ops::Range,
+  path::{Path, PathBuf},
+};
+
+use async_compression::tokio::bufread::GzipDecoder;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use mime_guess::Mime;
+use tokio::io::AsyncReadExt;
+use tokio_stream::StreamExt;
+use tokio_tar::{Archive, EntryType};
+
+use super::{Entry, Metadata, Mtime};
+use crate::{
+  errors::AppError,
+  utils::{
+    encrypt::{etag, get_intergrity},
+    fs::get_content_type,
+  },
+};
+
+/// A regular file inside a package tarball.
+#[derive(Debug, Clone)]
+pub struct IndexedFile {
+  pub path: String,
+  pub content_type: Mime,
+  pub integrity: String,
+  /// The `etag` of the content, so responses need not hash it again.
+  pub etag: String,
+  pub mtime: u64,
+  pub size: u64,
+  range: Range<usize>,
+}
+
+/// The files of one package version, parsed once from its tarball. File
+/// contents are byte ranges into the decompressed archive kept alongside.
+#[derive(Debug)]
+pub struct PackageIndex {
+  data: Bytes,
+  files: BTreeMap<String, IndexedFile>,
+}
+
+/// `package/lib/index.js` becomes `/lib/index.js`.
+#[inline]
+fn normalize_path(path: &Path) -> String {
+  let path = if path.starts_with("/") {
+    path.to_path_buf()
+  } else {
+    PathBuf::from("/").join(path.iter().skip(1).collect::<PathBuf>())
+  };
+  path.to_string_lossy().into_owned()
+}
+
+impl PackageIndex {
+  /// Fails with `PackageTooLarge` as soon as the decompressed archive grows
+  /// past `max_unpacked_size`.
+  pub async fn from_tarball(
+    package_spec: &str,
+    tarball: &[u8],
+    max_unpacked_size: u64,
+  ) -> anyhow::Result<Self> {
+    let mut data = Vec::new();
+    GzipDecoder::new(tarball)
+      .take(max_unpacked_size.saturating_add(1))
+      .read_to_end(&mut data)
+      .await?;
+    if data.len() as u64 > max_unpacked_size {
+      return Err(
+        AppError::PackageTooLarge {
+          package_spec: package_spec.to_owned(),
+          limit: max_unpacked_size,
+        }
+        .into(),
+      );
+    }
+    let data = Bytes::from(data);
+
+    let mut files = BTreeMap::new();
+    {
+      let mut ar = Archive::new(&data[..]);
+      let mut entries = ar.entries()?;
+      while let Some(file) = entries.next().await {
+        let file = file?;
+        if !file.header().entry_type().is_file() {
+          continue;
+        }
+
+        let path = normalize_path(&file.path()?);
+        let size = file.header().size()?;
+        let start = file.raw_file_position() as usize;
+        let range = start..start + size as usize;
+        let content = data
+          .get(range.clone())
+          .ok_or(anyhow::anyhow!("truncated tarball entry {path}"))?;
+
+        files.insert(
+          path.clone(),
+          IndexedFile {
+            content_type: get_content_type(&PathBuf::from(&path)),
+            integrity: get_intergrity(content)?,
+            etag: etag(content)?,
+            mtime: file.header().mtime()?,
+            path,
+            size,
+            range,
+          },
+        );
+      }
+    }
+
+    Ok(Self { data, files })
+  }
+
+  #[inline]
+  pub fn file(&self, path: &str) -> Option<&IndexedFile> {
+    self.files.get(path)
+  }
+
+  #[inline]
+  pub fn content(&self, file: &IndexedFile) -> Bytes {
+    self.data.slice(file.range.clone())
+  }
+
+  pub fn is_dir(&self, path: &str) -> bool {
+    path == "/" || self.files_in(path).next().is_some()
+  }
+
+  /// Every file below the directory `dir`, in path order.
+  pub fn files_in<'a>(&'a self, dir: &str) -> impl Iterator<Item = &'a IndexedFile> {
+    let prefix = format!("{}/", dir.trim_end_matches('/'));
+    self
+      .files
+      .range(prefix.clone()..)
+      .take_while(move |(path, _)| path.starts_with(&prefix))
+      .map(|(_, file)| file)
+  }
+
+  /// Size of the decompressed archive in bytes.
+  #[inline]
+  pub fn unpacked_size(&self) -> usize {
+    self.data.len()
+  }
+
+  pub fn entry(&self, path: &str) -> Option<Entry> {
+    let file = self.file(path)?;
+    Some(Entry {
+      content: self.content(file),
+      ..self.entry_without_content(path)?
+    })
+  }
+
+  /// An entry with everything but the content, enough for the headers of a
+  /// file response.
+  pub fn entry_without_content(&self, path: &str) -> Option<Entry> {
+    let file = self.file(path)?;
+    Some(Entry {
+      path: PathBuf::from(&file.path),
+      entry_type: EntryType::Regular,
+      content_type: file.content_type.clone(),
+      integrity: file.integrity.clone(),
+      etag: file.etag.clone(),
+      last_modified: DateTime::<Utc>::try_from(Mtime::from(file.mtime))
+        .ok()?
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string(),
+      size: file.size,
+      content: Default::default(),
+    })
+  }
+}
+
+impl TryFrom<&IndexedFile> for Metadata {
+  type Error = anyhow::Error;
+
+  fn try_from(file: &IndexedFile) -> Result<Self, Self::Error> {
+    Ok(Metadata::File {
+      path: PathBuf::from(&file.path),
+      content_type: file.content_type.clone(),
+      integrity: file.integrity.clone(),
+      last_modified: Mtime::from(file.mtime).try_into()?,
+      size: file.size,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::registry::create_tarball;
+
+  #[tokio::test]
+  async fn test_index_tarball() -> anyhow::Result<()> {
+    let tarball = create_tarball(&[
+      ("/package.json", "{}"),
+      ("/lib/index.js", "export default 1;"),
+      ("/lib/util/a.js", "a"),
+      ("/library.js", "library"),
+    ])
+    .await?;
+    let index = PackageIndex::from_tarball("pkg@1.0.0", &tarball, u64::MAX).await?;
+
+    let file = index.file("/lib/index.js").unwrap();
+    assert_eq!(file.size, 17);
+    assert_eq!(file.etag, etag("export default 1;")?);
+    assert_eq!(index.content(file), "export default 1;");
+    assert_eq!(index.entry("/library.js").unwrap().content, "library");
+    let entry = index.entry_without_content("/library.js").unwrap();
+    assert_eq!((entry.size, entry.content.len()), (7, 0));
+
+    assert!(index.is_dir("/"));
+    assert!(index.is_dir("/lib"));
+    assert!(!index.is_dir("/lib/index.js"));
+    assert_eq!(
+      index
+        .files_in("/lib")
+        .map(|f| &f.path[..])
+        .collect::<Vec<_>>(),
+      vec!["/lib/index.js", "/lib/util/a.js"]
+    );
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_max_unpacked_size() -> anyhow::Result<()> {
+    let content = "a".repeat(4096);
+    let tarball = create_tarball(&[("/index.js", &content)]).await?;
+    let error = PackageIndex::from_tarball("pkg@1.0.0", &tarball, 4096)
+      .await
+      .unwrap_err();
+    assert!(matches!(
+      AppError::from(error),
+      AppError::PackageTooLarge { limit: 4096, .. }
+    ));
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_corrupt_tarball() -> anyhow::Result<()> {
+    use async_compression::tokio::write::GzipEncoder;
+    use tokio::io::AsyncWriteExt;
+
+    let tarball = create_tarball(&[("/a.js", "a"), ("/b.js", "b")]).await?;
+    let mut tar = Vec::new();
+    GzipDecoder::new(&tarball[..]).read_to_end(&mut tar).await?;
+    // The second header follows the first one and its padded content.
+    tar[1024] ^= 0xff;
+    let mut encoder = GzipEncoder::new(Vec::new());
+    encoder.write_all(&tar).await?;
+    encoder.shutdown().await?;
+
+    let result = PackageIndex::from_tarball("pkg@1.0.0", &encoder.into_inner(), u64::MAX).await;
+    assert!(result.is_err());
+    Ok(())
+  }
+}
@@ -0,0 +1,336 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use node_semver::{Range, Version};
+use poem::{FromRequest, Request, RequestBody};
+use regex::Regex;
+
+use crate::{
+  config::{PolicyConfig, PolicyRule},
+  errors::AppError,
+  models::PackageConfig,
+  utils::npm::NpmClient,
+};
+
+struct Rule {
+  package: Regex,
+  versions: Option<Range>,
+  reason: Option<String>,
+}
+
+impl Rule {
+  fn new(rule: &PolicyRule) -> anyhow::Result<Self> {
+    let pattern = rule
+      .package
+      .split('*')
+      .map(regex::escape)
+      .collect::<Vec<_>>()
+      .join(".*");
+    let versions = rule
+      .versions
+      .as_deref()
+      .map(Range::parse)
+      .transpose()
+      .with_context(|| format!("invalid version range for \"{}\"", rule.package))?;
+    Ok(Self {
+      package: Regex::new(&format!("^{pattern}$"))?,
+      versions,
+      reason: rule.reason.clone(),
+    })
+  }
+
+  /// A rule with a range never matches a version that is not valid semver.
+  fn matches(&self, package_name: &str, version: Option<&str>) -> bool {
+    self.package.is_match(package_name)
+      && match (&self.versions, version) {
+        (None, _) => true,
+        (Some(range), Some(version)) => {
+          Version::parse(version).is_ok_and(|version| range.satisfies(&version))
+        }
+        (Some(_), None) => false,
+      }
+  }
+}
+
+/// Which packages, versions and licenses may be served, compiled from
+/// `PolicyConfig`.
+pub struct Policy {
+  allow: Vec<Rule>,
+  deny: Vec<Rule>,
+  licenses: Vec<String>,
+}
+
+impl Policy {
+  pub fn new(config: &PolicyConfig) -> anyhow::Result<Self> {
+    Ok(Self {
+      allow: config
+        .allow
+        .iter()
+        .map(Rule::new)
+        .collect::<Result<_, _>>()?,
+      deny: config
+        .deny
+        .iter()
+        .map(Rule::new)
+        .collect::<Result<_, _>>()?,
+      licenses: config.licenses.clone(),
+    })
+  }
+
+  fn blocked(package_spec: String, rule: Option<&Rule>) -> AppError {
+    let reason = match rule {
+      Some(rule) => rule.reason.as_deref().unwrap_or("denied by policy"),
+      None => "not on the allow list",
+    };
+    AppError::PackageBlocked {
+      package_spec,
+      reason: reason.to_owned(),
+    }
+  }
+
+  /// Rejects a package when every version of it is blocked, before anything is
+  /// fetched.
+  pub fn check_package(&self, package_name: &str) -> Result<(), AppError> {
+    if let Some(rule) = self
+      .deny
+      .iter()
+      .find(|rule| rule.matches(package_name, None))
+    {
+      return Err(Self::blocked(package_name.to_owned(), Some(rule)));
+    }
+    let allowed = self.allow.is_empty()
+      || self
+        .allow
+        .iter()
+        .any(|rule| rule.package.is_match(package_name));
+    match allowed {
+      true => Ok(()),
+      false => Err(Self::blocked(package_name.to_owned(), None)),
+    }
+  }
+
+  pub fn check_version(&self, package_name: &str, version: &str) -> Result<(), AppError> {
+    let package_spec = || format!("{package_name}@{version}");
+    if let Some(rule) = self
+      .deny
+      .iter()
+      .find(|rule| rule.matches(package_name, Some(version)))
+    {
+      return Err(Self::blocked(package_spec(), Some(rule)));
+    }
+    let allowed = self.allow.is_empty()
+      || self
+        .allow
+        .iter()
+        .any(|rule| rule.matches(package_name, Some(version)));
+    match allowed {
+      true => Ok(()),
+      false => Err(Self::blocked(package_spec(), None)),
+    }
+  }
+
+  pub fn check_license(&self, package_spec: &str, config: &PackageConfig) -> Result<(), AppError> {
+    if self.licenses.is_empty() {
+      return Ok(());
+    }
+    match config.license() {
+      Some(license) if license_allowed(&license, &self.licenses) => Ok(()),
+      license => Err(AppError::LicenseNotAllowed {
+        package_spec: package_spec.to_owned(),
+        license: license.unwrap_or_else(|| "none".into()),
+      }),
+    }
+  }
+
+  /// Checks a version and its license.
+  pub fn check(
+    &self,
+    package_name: &str,
+    version: &str,
+    config: &PackageConfig,
+  ) -> Result<(), AppError> {
+    self.check_version(package_name, version)?;
+    self.check_license(&format!("{package_name}@{version}"), config)
+  }
+
+  /// The `versions` of a package that pass `check`. The packument is only
+  /// fetched when licenses are checked.
+  pub async fn allowed_versions(
+    &self,
+    npm: &NpmClient,
+    package_name: &str,
+    versions: Vec<String>,
+  ) -> Result<Vec<String>, AppError> {
+    let versions = versions
+      .into_iter()
+      .filter(|version| self.check_version(package_name, version).is_ok());
+    if self.licenses.is_empty() {
+      return Ok(versions.collect());
+    }
+    let packument = npm.get_packument(package_name).await?;
+    Ok(
+      versions
+        .filter(|version| {
+          packument.versions.get(version).is_some_and(|config| {
+            let package_spec = format!("{package_name}@{version}");
+            self.check_license(&package_spec, config).is_ok()
+          })
+        })
+        .collect(),
+    )
+  }
+}
+
+#[poem::async_trait]
+impl<'a> FromRequest<'a> for &'a Policy {
+  async fn from_request(req: &'a Request, _: &mut RequestBody) -> poem::Result<Self> {
+    req
+      .extensions()
+      .get::<Arc<Policy>>()
+      .map(|policy| policy.as_ref())
+      .ok_or(anyhow::anyhow!("get policy from the request extensions"))
+      .map_err(Into::into)
+  }
+}
+
+#[inline]
+fn license_id_allowed(id: &str, allowed: &[String]) -> bool {
+  allowed
+    .iter()
+    .any(|a| a.eq_ignore_ascii_case(id) || a.eq_ignore_ascii_case(id.trim_end_matches('+')))
+}
+
+/// Evaluates an SPDX expression like `(MIT OR GPL-3.0-only)`: `OR` needs one
+/// side allowed and `AND` both. A `WITH` exception is judged by its license.
+/// Anything that does not parse, e.g. `SEE LICENSE IN LICENSE.md`, must be
+/// allowed verbatim.
+fn license_allowed(expression: &str, allowed: &[String]) -> bool {
+  let tokens = regex!(r"[()]|[^\s()]+")
+    .find_iter(expression)
+    .map(|m| m.as_str())
+    .collect::<Vec<_>>();
+  let mut parser = SpdxParser {
+    tokens: &tokens,
+    pos: 0,
+    allowed,
+  };
+  match parser.or() {
+    Some(result) if parser.pos == tokens.len() => result,
+    _ => license_id_allowed(expression.trim(), allowed),
+  }
+}
+
+struct SpdxParser<'a> {
+  tokens: &'a [&'a str],
+  pos: usize,
+  allowed: &'a [String],
+}
+
+impl SpdxParser<'_> {
+  fn eat(&mut self, token: &str) -> bool {
+    let found = self
+      .tokens
+      .get(self.pos)
+      .is_some_and(|t| t.eq_ignore_ascii_case(token));
+    if found {
+      self.pos += 1;
+    }
+    found
+  }
+
+  fn or(&mut self) -> Option<bool> {
+    let mut result = self.and()?;
+    while self.eat("OR") {
+      result |= self.and()?;
+    }
+    Some(result)
+  }
+
+  fn and(&mut self) -> Option<bool> {
+    let mut result = self.term()?;
+    while self.eat("AND") {
+      result &= self.term()?;
+    }
+    Some(result)
+  }
+
+  fn term(&mut self) -> Option<bool> {
+    if self.eat("(") {
+      let result = self.or()?;
+      return self.eat(")").then_some(result);
+    }
+    let id = *self.tokens.get(self.pos)?;
+    if id == ")"
+      || ["AND", "OR", "WITH"]
+        .iter()
+        .any(|k| id.eq_ignore_ascii_case(k))
+    {
+      return None;
+    }
+    self.pos += 1;
+    if self.eat("WITH") {
+      self.tokens.get(self.pos)?;
+      self.pos += 1;
+    }
+    Some(license_id_allowed(id, self.allowed))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn rule(package: &str, versions: Option<&str>) -> PolicyRule {
+    PolicyRule {
+      package: package.into(),
+      versions: versions.map(Into::into),
+      reason: None,
+    }
+  }
+
+  #[test]
+  fn test_rules() -> anyhow::Result<()> {
+    let policy = Policy::new(&PolicyConfig {
+      deny: vec![rule("@evil/*", None), rule("event-stream", Some("3.3.6"))],
+      ..Default::default()
+    })?;
+    assert!(policy.check_package("@evil/pkg").is_err());
+    assert!(policy.check_package("@evil-twin/pkg").is_ok());
+    assert!(policy.check_package("event-stream").is_ok());
+    assert!(policy.check_version("event-stream", "3.3.6").is_err());
+    assert!(policy.check_version("event-stream", "3.3.5").is_ok());
+
+    let policy = Policy::new(&PolicyConfig {
+      allow: vec![rule("react*", None), rule("lodash", Some("^4.17.21"))],
+      ..Default::default()
+    })?;
+    assert!(policy.check_version("react-dom", "18.2.0").is_ok());
+    assert!(policy.check_package("lodash").is_ok());
+    assert!(policy.check_version("lodash", "4.17.20").is_err());
+    assert!(policy.check_package("vue").is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn test_license_allowed() {
+    let allowed = [
+      "MIT".to_owned(),
+      "Apache-2.0".to_owned(),
+      "GPL-2.0".to_owned(),
+    ];
+    assert!(license_allowed("MIT", &allowed));
+    assert!(license_allowed("mit", &allowed));
+    assert!(license_allowed("(MIT OR GPL-3.0-only)", &allowed));
+    assert!(license_allowed(
+      "MIT AND (Apache-2.0 OR BSD-3-Clause)",
+      &allowed
+    ));
+    assert!(license_allowed(
+      "GPL-2.0+ WITH Classpath-exception-2.0",
+      &allowed
+    ));
+    assert!(!license_allowed("MIT AND GPL-3.0-only", &allowed));
+    assert!(!license_allowed("SEE LICENSE IN LICENSE.md", &allowed));
+    assert!(!license_allowed("(MIT OR", &allowed));
+  }
+}
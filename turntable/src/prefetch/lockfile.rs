@@ -0,0 +1,332 @@
+use std::{collections::BTreeSet, fmt, path::Path};
+
+use serde_json::{Map, Value};
+
+/// A package and the version, range or tag to fetch.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PackageSpec {
+  pub name: String,
+  pub version: String,
+}
+
+impl fmt::Display for PackageSpec {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}@{}", self.name, self.version)
+  }
+}
+
+impl PackageSpec {
+  /// Parses `name@range`, `@scope/name@range` or a bare name meaning `latest`.
+  pub fn parse(spec: &str) -> Self {
+    match split_name(spec) {
+      Some((name, version)) => Self {
+        name: name.to_owned(),
+        version: version.to_owned(),
+      },
+      None => Self {
+        name: spec.to_owned(),
+        version: "latest".to_owned(),
+      },
+    }
+  }
+
+  /// Follows `npm:` aliases and drops versions that are not served by a
+  /// registry, like `file:`, `link:`, git or tarball URLs.
+  fn from_registry(name: &str, version: &str) -> Option<Self> {
+    let (name, version) = match version.strip_prefix("npm:") {
+      Some(alias) => split_name(alias).unwrap_or((name, alias)),
+      None => (name, version),
+    };
+    if name.is_empty() || name.contains(':') || version.is_empty() || version.contains([':', '/']) {
+      return None;
+    }
+    Some(Self {
+      name: name.to_owned(),
+      version: version.to_owned(),
+    })
+  }
+}
+
+/// Splits `@scope/name@1.0.0` at the `@` separating the version.
+#[inline]
+fn split_name(spec: &str) -> Option<(&str, &str)> {
+  let at = spec.get(1..)?.find('@')? + 1;
+  Some((&spec[..at], &spec[at + 1..]))
+}
+
+/// Reads the packages of a lockfile, picking the format by file name. Files
+/// other than `package-lock.json`, `npm-shrinkwrap.json`, `pnpm-lock.yaml` and
+/// `yarn.lock` are read as one spec per line.
+pub fn parse(path: &Path, content: &str) -> anyhow::Result<Vec<PackageSpec>> {
+  let specs = match path.file_name().and_then(|name| name.to_str()) {
+    Some("package-lock.json" | "npm-shrinkwrap.json") => parse_package_lock(content)?,
+    Some("pnpm-lock.yaml") => parse_pnpm_lock(content),
+    Some("yarn.lock") => parse_yarn_lock(content),
+    _ => parse_list(content),
+  };
+  Ok(specs.into_iter().collect())
+}
+
+fn parse_package_lock(content: &str) -> anyhow::Result<BTreeSet<PackageSpec>> {
+  let lock: Value = serde_json::from_str(content)?;
+  let mut specs = BTreeSet::new();
+
+  // lockfileVersion 2 and 3 list every install path under `packages`.
+  if let Some(packages) = lock["packages"].as_object() {
+    for (path, entry) in packages {
+      let Some((_, name)) = path.rsplit_once("node_modules/") else {
+        continue;
+      };
+      if entry["link"].as_bool() == Some(true) {
+        continue;
+      }
+      let name = entry["name"].as_str().unwrap_or(name);
+      if let Some(version) = entry["version"].as_str() {
+        specs.extend(PackageSpec::from_registry(name, version));
+      }
+    }
+    return Ok(specs);
+  }
+
+  // lockfileVersion 1 nests `dependencies`.
+  fn collect(dependencies: &Map<String, Value>, specs: &mut BTreeSet<PackageSpec>) {
+    for (name, entry) in dependencies {
+      if let Some(version) = entry["version"].as_str() {
+        specs.extend(PackageSpec::from_registry(name, version));
+      }
+      if let Some(dependencies) = entry["dependencies"].as_object() {
+        collect(dependencies, specs);
+      }
+    }
+  }
+  if let Some(dependencies) = lock["dependencies"].as_object() {
+    collect(dependencies, &mut specs);
+  }
+  Ok(specs)
+}
+
+/// Reads the keys of the `packages` map: `/name/1.0.0_peer@2.0.0` (v5),
+/// `/name@1.0.0(peer@2.0.0)` (v6) or `name@1.0.0` (v9).
+fn parse_pnpm_lock(content: &str) -> BTreeSet<PackageSpec> {
+  let mut specs = BTreeSet::new();
+  let mut in_packages = false;
+  let mut v5 = false;
+
+  for line in content.lines() {
+    if let Some(version) = line.strip_prefix("lockfileVersion:") {
+      v5 = version.trim().trim_matches(['\'', '"']).starts_with('5');
+    }
+    if !line.starts_with(' ') && !line.is_empty() {
+      in_packages = line.trim_end() == "packages:";
+      continue;
+    }
+    let Some(key) = line.strip_prefix("  ") else {
+      continue;
+    };
+    if !in_packages || key.starts_with(' ') {
+      continue;
+    }
+
+    let key = key
+      .trim_end()
+      .trim_end_matches(':')
+      .trim_matches(['\'', '"']);
+    let key = key.strip_prefix('/').unwrap_or(key);
+    let key = key.split('(').next().unwrap_or_default();
+    let spec = match v5 {
+      true => key.rsplit_once('/').and_then(|(name, version)| {
+        let version = version.split('_').next().unwrap_or_default();
+        PackageSpec::from_registry(name, version)
+      }),
+      false => {
+        split_name(key).and_then(|(name, version)| PackageSpec::from_registry(name, version))
+      }
+    };
+    specs.extend(spec);
+  }
+  specs
+}
+
+/// Reads both the classic (`version "1.0.0"`) and the Berry
+/// (`version: 1.0.0`) format.
+fn parse_yarn_lock(content: &str) -> BTreeSet<PackageSpec> {
+  let mut specs = BTreeSet::new();
+  let mut name = None;
+
+  for line in content.lines() {
+    if line.starts_with('#') || line.trim().is_empty() {
+      continue;
+    }
+    if !line.starts_with(' ') {
+      // `"a@^1.0.0", a@^1.1.0:` or `"a@npm:^1.0.0":`
+      let first = line
+        .trim_end_matches(':')
+        .split(',')
+        .next()
+        .unwrap_or_default();
+      name = split_name(first.trim().trim_matches('"')).and_then(|(name, range)| {
+        let range = range.strip_prefix("npm:").unwrap_or(range);
+        match split_name(range) {
+          Some((aliased, _)) => Some(aliased.to_owned()),
+          None if range.contains(':') => None,
+          None => Some(name.to_owned()),
+        }
+      });
+      continue;
+    }
+
+    let line = line.trim();
+    let Some(version) = line
+      .strip_prefix("version ")
+      .or_else(|| line.strip_prefix("version: "))
+    else {
+      continue;
+    };
+    if let Some(name) = name.take() {
+      specs.extend(PackageSpec::from_registry(&name, version.trim_matches('"')));
+    }
+  }
+  specs
+}
+
+fn parse_list(content: &str) -> BTreeSet<PackageSpec> {
+  content
+    .lines()
+    .map(|line| line.split('#').next().unwrap_or_default().trim())
+    .filter(|line| !line.is_empty())
+    .map(PackageSpec::parse)
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn specs(path: &str, content: &str) -> Vec<String> {
+    parse(Path::new(path), content)
+      .unwrap()
+      .iter()
+      .map(ToString::to_string)
+      .collect()
+  }
+
+  #[test]
+  fn test_parse_package_lock() {
+    let content = r#"{
+      "lockfileVersion": 3,
+      "packages": {
+        "": { "name": "app", "dependencies": { "react": "^18.0.0" } },
+        "node_modules/react": { "version": "18.2.0" },
+        "node_modules/@scope/a": { "version": "1.0.0" },
+        "node_modules/@scope/a/node_modules/b": { "version": "2.0.0" },
+        "node_modules/old": { "name": "b", "version": "1.0.0" },
+        "node_modules/local": { "resolved": "packages/local", "link": true },
+        "node_modules/git": { "version": "git+ssh://git@github.com/a/b.git#abc" }
+      }
+    }"#;
+    assert_eq!(
+      specs("package-lock.json", content),
+      ["@scope/a@1.0.0", "b@1.0.0", "b@2.0.0", "react@18.2.0"]
+    );
+
+    let content = r#"{
+      "lockfileVersion": 1,
+      "dependencies": {
+        "a": { "version": "1.0.0", "dependencies": { "b": { "version": "2.0.0" } } },
+        "c": { "version": "npm:d@3.0.0" }
+      }
+    }"#;
+    assert_eq!(
+      specs("npm-shrinkwrap.json", content),
+      ["a@1.0.0", "b@2.0.0", "d@3.0.0"]
+    );
+  }
+
+  #[test]
+  fn test_parse_pnpm_lock() {
+    let content = "\
+lockfileVersion: '6.0'
+
+dependencies:
+  react:
+    specifier: ^18.0.0
+    version: 18.2.0
+
+packages:
+
+  /@scope/a@1.0.0(react@18.2.0):
+    resolution: {integrity: sha512-a}
+    dependencies:
+      react: 18.2.0
+    dev: false
+
+  /react@18.2.0:
+    resolution: {integrity: sha512-b}
+
+  'b@3.0.0':
+    resolution: {integrity: sha512-c}
+
+  file:packages/local:
+    resolution: {directory: packages/local, type: directory}
+";
+    assert_eq!(
+      specs("pnpm-lock.yaml", content),
+      ["@scope/a@1.0.0", "b@3.0.0", "react@18.2.0"]
+    );
+
+    let content = "\
+lockfileVersion: 5.4
+
+packages:
+
+  /a/1.0.0_react@18.2.0:
+    resolution: {integrity: sha512-a}
+
+  /@scope/b/2.0.0:
+    resolution: {integrity: sha512-b}
+";
+    assert_eq!(
+      specs("pnpm-lock.yaml", content),
+      ["@scope/b@2.0.0", "a@1.0.0"]
+    );
+  }
+
+  #[test]
+  fn test_parse_yarn_lock() {
+    let content = r#"# yarn lockfile v1
+
+
+"@scope/a@^1.0.0", "@scope/a@^1.0.1":
+  version "1.0.2"
+  resolved "https://registry.yarnpkg.com/@scope/a/-/a-1.0.2.tgz"
+
+alias@npm:b@^2.0.0:
+  version "2.1.0"
+
+c@github:user/c:
+  version "0.0.0"
+"#;
+    assert_eq!(specs("yarn.lock", content), ["@scope/a@1.0.2", "b@2.1.0"]);
+
+    let content = r#"__metadata:
+  version: 6
+
+"app@workspace:.":
+  version: 0.0.0-use.local
+
+"react@npm:^18.0.0":
+  version: 18.2.0
+  resolution: "react@npm:18.2.0"
+"#;
+    assert_eq!(specs("yarn.lock", content), ["react@18.2.0"]);
+  }
+
+  #[test]
+  fn test_parse_list() {
+    let content = "react@^18\n\n# comment\n@scope/a\nlodash@4.17.21 # pinned\n";
+    assert_eq!(
+      specs("packages.txt", content),
+      ["@scope/a@latest", "lodash@4.17.21", "react@^18"]
+    );
+  }
+}
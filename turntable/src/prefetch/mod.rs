@@ -0,0 +1,201 @@
+mod lockfile;
+
+use std::sync::Arc;
+
+use anyhow::Context;
+use tokio::task::JoinSet;
+
+pub use lockfile::*;
+
+use crate::{
+  config::Config, errors::AppError, middlewares::resolve_version, policy::Policy,
+  registry::Registry, utils::npm::NpmClient,
+};
+
+/// A package that could not be prefetched.
+#[derive(Debug)]
+pub struct Failure {
+  pub spec: PackageSpec,
+  pub error: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+  /// Resolved `name@version` of every tarball now in the cache.
+  pub fetched: Vec<String>,
+  pub failures: Vec<Failure>,
+}
+
+/// Resolves every spec, downloads and verifies its tarball and stores it in
+/// the cache, `concurrency` packages at a time.
+pub async fn prefetch(
+  config: &Config,
+  registry: Arc<dyn Registry>,
+  specs: Vec<PackageSpec>,
+  concurrency: usize,
+) -> anyhow::Result<Report> {
+  if config.cache.dir.is_none() {
+    anyhow::bail!("prefetch needs a cache directory (cache.dir)");
+  }
+  let npm = Arc::new(NpmClient::new(registry, config)?);
+  let policy = Arc::new(Policy::new(&config.policy)?);
+
+  let mut report = Report::default();
+  let mut tasks = JoinSet::new();
+  let mut specs = specs.into_iter();
+  loop {
+    while tasks.len() < concurrency.max(1) {
+      let Some(spec) = specs.next() else {
+        break;
+      };
+      let npm = npm.clone();
+      let policy = policy.clone();
+      tasks.spawn(async move {
+        let result = prefetch_one(&npm, &policy, &spec).await;
+        (spec, result)
+      });
+    }
+
+    let Some(joined) = tasks.join_next().await else {
+      break;
+    };
+    match joined? {
+      (_, Ok(package_spec)) => {
+        tracing::debug!("Prefetched {package_spec}");
+        report.fetched.push(package_spec);
+      }
+      (spec, Err(e)) => report.failures.push(Failure {
+        spec,
+        error: format!("{e:#}"),
+      }),
+    }
+  }
+  Ok(report)
+}
+
+async fn prefetch_one(
+  npm: &NpmClient,
+  policy: &Policy,
+  spec: &PackageSpec,
+) -> anyhow::Result<String> {
+  let (version, _) = resolve_version(npm, policy, &spec.name, &spec.version).await?;
+  let version = version.ok_or_else(|| AppError::NotFoundPackage(spec.to_string()))?;
+  let package_spec = format!("{}@{version}", spec.name);
+
+  let package_config = npm
+    .get_package_config(&spec.name, &version)
+    .await
+    .ok_or_else(|| AppError::UnableGetConfigForPackage(package_spec.clone()))?;
+  policy.check_license(&package_spec, &package_config)?;
+  let integrity = package_config
+    .integrity()
+    .context("the registry has no integrity for this version, so it cannot be cached")?;
+  npm.cache_package(&spec.name, &version, integrity).await?;
+  Ok(package_spec)
+}
+
+#[cfg(test)]
+mod tests {
+  use bytes::Bytes;
+
+  use super::*;
+  use crate::{
+    cache::TarballCache,
+    config::{CacheConfig, PolicyConfig},
+    registry::MemoryRegistry,
+  };
+
+  #[tokio::test]
+  async fn test_prefetch_fills_cache() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let config = Config {
+      cache: CacheConfig {
+        dir: Some(dir.path().to_path_buf()),
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    let registry = MemoryRegistry::new();
+    for version in ["1.0.0", "1.1.0", "2.0.0"] {
+      registry.insert(
+        serde_json::json!({ "name": "pkg", "version": version }),
+        Bytes::from(format!("pkg@{version}")),
+      )?;
+    }
+    let registry: Arc<dyn Registry> = Arc::new(registry);
+
+    let specs = ["pkg@^1.0.0", "pkg@2.0.0", "missing@1.0.0"]
+      .into_iter()
+      .map(PackageSpec::parse)
+      .collect();
+    let mut report = prefetch(&config, registry.clone(), specs, 2).await?;
+    report.fetched.sort();
+    assert_eq!(report.fetched, ["pkg@1.1.0", "pkg@2.0.0"]);
+    assert_eq!(report.failures.len(), 1);
+    assert_eq!(report.failures[0].spec.name, "missing");
+
+    let npm = NpmClient::new(registry, &config)?;
+    let integrity = npm
+      .get_package_config("pkg", "1.1.0")
+      .await
+      .unwrap()
+      .integrity();
+    let cache = TarballCache::open(&config.cache)?;
+    assert!(cache.get("pkg@1.1.0", &integrity.unwrap()).await.is_some());
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_prefetch_reports_uncacheable_tarballs() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let config = Config {
+      cache: CacheConfig {
+        dir: Some(dir.path().to_path_buf()),
+        max_size: 4,
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    let registry = MemoryRegistry::new();
+    registry.insert(
+      serde_json::json!({ "name": "pkg", "version": "1.0.0" }),
+      Bytes::from_static(b"larger than the cache"),
+    )?;
+
+    let specs = vec![PackageSpec::parse("pkg@1.0.0")];
+    let report = prefetch(&config, Arc::new(registry), specs, 1).await?;
+    assert!(report.fetched.is_empty());
+    assert_eq!(report.failures.len(), 1);
+    assert!(report.failures[0].error.contains("max_size"));
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_prefetch_skips_license_blocked_versions() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let config = Config {
+      cache: CacheConfig {
+        dir: Some(dir.path().to_path_buf()),
+        ..Default::default()
+      },
+      policy: PolicyConfig {
+        licenses: vec!["MIT".into()],
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    let registry = MemoryRegistry::new();
+    for (version, license) in [("1.0.0", "MIT"), ("1.1.0", "GPL-3.0-only")] {
+      registry.insert(
+        serde_json::json!({ "name": "pkg", "version": version, "license": license }),
+        Bytes::from(format!("pkg@{version}")),
+      )?;
+    }
+
+    let specs = vec![PackageSpec::parse("pkg@^1.0.0")];
+    let report = prefetch(&config, Arc::new(registry), specs, 1).await?;
+    assert_eq!(report.fetched, ["pkg@1.0.0"]);
+    assert!(report.failures.is_empty());
+    Ok(())
+  }
+}
@@ -0,0 +1,191 @@
+use std::{
+  collections::HashMap,
+  io::ErrorKind,
+  path::{Path, PathBuf},
+};
+
+use bytes::Bytes;
+use node_semver::Version;
+use tokio::{fs, sync::Mutex};
+
+use super::{tarball_filename, PackageConfig, Packument, Registry};
+use crate::{errors::AppError, utils::fs::write_atomic};
+
+/// A registry backed by a local directory laid out like
+/// `<root>/<name>/packument.json` and `<root>/<name>/-/<name>-<version>.tgz`.
+pub struct FsRegistry {
+  root: PathBuf,
+  publish_lock: Mutex<()>,
+}
+
+impl FsRegistry {
+  pub fn new(root: impl Into<PathBuf>) -> Self {
+    Self {
+      root: root.into(),
+      publish_lock: Mutex::new(()),
+    }
+  }
+
+  pub fn root(&self) -> &Path {
+    &self.root
+  }
+
+  fn package_dir(&self, package_name: &str) -> anyhow::Result<PathBuf> {
+    if package_name
+      .split('/')
+      .any(|s| s.is_empty() || s == "." || s == "..")
+    {
+      anyhow::bail!("Invalid package name {package_name}");
+    }
+    Ok(self.root.join(package_name))
+  }
+
+  pub(crate) fn packument_path(&self, package_name: &str) -> anyhow::Result<PathBuf> {
+    Ok(self.package_dir(package_name)?.join("packument.json"))
+  }
+
+  pub(crate) fn tarball_path(&self, package_name: &str, version: &str) -> anyhow::Result<PathBuf> {
+    if Version::parse(version).is_err() || version.contains(['/', '\\']) {
+      anyhow::bail!("Invalid version {version}");
+    }
+    Ok(
+      self
+        .package_dir(package_name)?
+        .join("-")
+        .join(tarball_filename(package_name, version)),
+    )
+  }
+
+  pub async fn contains(&self, package_name: &str) -> bool {
+    match self.packument_path(package_name) {
+      Ok(path) => fs::try_exists(path).await.unwrap_or(false),
+      Err(_) => false,
+    }
+  }
+
+  /// Adds a version and its tarball, creating the package on its first
+  /// publish. Published versions cannot be replaced.
+  pub async fn publish(
+    &self,
+    package_name: &str,
+    version: &str,
+    config: PackageConfig,
+    dist_tags: HashMap<String, String>,
+    tarball: &[u8],
+  ) -> anyhow::Result<()> {
+    let _guard = self.publish_lock.lock().await;
+
+    let mut packument = match self.contains(package_name).await {
+      true => self.packument(package_name).await?,
+      false => Packument {
+        rest: [("name".to_owned(), package_name.into())].into_iter().collect(),
+        ..Default::default()
+      },
+    };
+    if packument.versions.contains_key(version) {
+      return Err(AppError::VersionAlreadyPublished(format!("{package_name}@{version}")).into());
+    }
+    packument.versions.insert(version.to_owned(), config);
+    if let Some((tag, target)) = dist_tags
+      .iter()
+      .find(|(_, target)| !packument.versions.contains_key(*target))
+    {
+      let reason = format!("dist-tag {tag} points at missing version {target}");
+      return Err(AppError::InvalidPublish(reason).into());
+    }
+    packument.dist_tags.extend(dist_tags);
+
+    write_atomic(&self.tarball_path(package_name, version)?, tarball).await?;
+    write_atomic(
+      &self.packument_path(package_name)?,
+      &serde_json::to_vec_pretty(&packument)?,
+    )
+    .await
+  }
+}
+
+#[poem::async_trait]
+impl Registry for FsRegistry {
+  async fn packument(&self, package_name: &str) -> anyhow::Result<Packument> {
+    let path = self.packument_path(package_name)?;
+    tracing::debug!("Reading package info for {package_name} from {path:?}");
+
+    let content = match fs::read(path).await {
+      Err(e) if e.kind() == ErrorKind::NotFound => {
+        return Err(AppError::NotFoundPackage(package_name.to_owned()).into())
+      }
+      result => result?,
+    };
+    Ok(serde_json::from_slice(&content)?)
+  }
+
+  async fn tarball(&self, package_name: &str, version: &str) -> anyhow::Result<Bytes> {
+    let path = self.tarball_path(package_name, version)?;
+    tracing::debug!("Reading package for {package_name} from {path:?}");
+
+    match fs::read(path).await {
+      Err(e) if e.kind() == ErrorKind::NotFound => {
+        Err(AppError::NotFoundPackage(format!("{package_name}@{version}")).into())
+      }
+      result => Ok(result?.into()),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn test_fs_registry() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let registry = FsRegistry::new(dir.path());
+
+    std::fs::create_dir_all(dir.path().join("@scope/name/-"))?;
+    std::fs::write(
+      dir.path().join("@scope/name/packument.json"),
+      r#"{"name":"@scope/name","dist-tags":{"latest":"1.0.0"},"versions":{"1.0.0":{}}}"#,
+    )?;
+    std::fs::write(dir.path().join("@scope/name/-/name-1.0.0.tgz"), "tarball")?;
+
+    let packument = registry.packument("@scope/name").await?;
+    assert!(packument.versions.contains_key("1.0.0"));
+    assert_eq!(packument.dist_tags["latest"], "1.0.0");
+    assert_eq!(registry.tarball("@scope/name", "1.0.0").await?, "tarball");
+    assert!(matches!(
+      registry
+        .tarball("@scope/name", "2.0.0")
+        .await
+        .map_err(AppError::from),
+      Err(AppError::NotFoundPackage(_))
+    ));
+    assert!(registry.packument("../name").await.is_err());
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_publish() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let registry = FsRegistry::new(dir.path());
+    let config: PackageConfig = serde_json::from_str(r#"{"name":"@scope/name","version":"1.0.0"}"#)?;
+    let tags = HashMap::from([("latest".to_owned(), "1.0.0".to_owned())]);
+
+    assert!(!registry.contains("@scope/name").await);
+    registry
+      .publish("@scope/name", "1.0.0", config.clone(), tags.clone(), b"tarball")
+      .await?;
+    assert!(registry.contains("@scope/name").await);
+    assert_eq!(registry.tarball("@scope/name", "1.0.0").await?, "tarball");
+
+    let result = registry
+      .publish("@scope/name", "1.0.0", config, tags, b"other")
+      .await;
+    assert!(matches!(
+      result.map_err(AppError::from),
+      Err(AppError::VersionAlreadyPublished(_))
+    ));
+    let packument = registry.packument("@scope/name").await?;
+    assert_eq!(packument.rest["name"], "@scope/name");
+    Ok(())
+  }
+}
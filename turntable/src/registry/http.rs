@@ -0,0 +1,241 @@
+use std::time::Duration;
+
+use bytes::{Bytes, BytesMut};
+use reqwest::{RequestBuilder, StatusCode};
+
+use super::{encode_package_name, tarball_filename, Packument, Registry};
+use crate::{config::LimitsConfig, errors::AppError};
+
+#[derive(Clone)]
+pub enum RegistryAuth {
+  Bearer(String),
+  Basic { username: String, password: String },
+}
+
+/// A registry speaking the npm registry HTTP API.
+pub struct HttpRegistry {
+  url: String,
+  auth: Option<RegistryAuth>,
+  client: reqwest::Client,
+  read_timeout: Duration,
+  max_tarball_size: u64,
+}
+
+#[inline]
+fn create_client(limits: &LimitsConfig) -> reqwest::Client {
+  reqwest::ClientBuilder::new()
+    .tcp_keepalive(Duration::from_secs(1))
+    .connect_timeout(Duration::from_secs(limits.connect_timeout))
+    .timeout(Duration::from_secs(limits.timeout))
+    .build()
+    .expect("init reqwest client ok!")
+}
+
+/// Timeouts surface as `UpstreamTimeout`, whichever limit was hit.
+#[inline]
+fn map_timeout(error: reqwest::Error, what: &str) -> anyhow::Error {
+  match error.is_timeout() {
+    true => AppError::UpstreamTimeout(what.to_owned()).into(),
+    false => error.into(),
+  }
+}
+
+impl HttpRegistry {
+  pub fn new(url: impl Into<String>) -> Self {
+    let limits = LimitsConfig::default();
+    Self {
+      url: url.into(),
+      auth: None,
+      client: create_client(&limits),
+      read_timeout: Duration::from_secs(limits.read_timeout),
+      max_tarball_size: limits.max_tarball_size,
+    }
+  }
+
+  pub fn with_auth(mut self, auth: RegistryAuth) -> Self {
+    self.auth = Some(auth);
+    self
+  }
+
+  pub fn with_limits(mut self, limits: &LimitsConfig) -> Self {
+    self.client = create_client(limits);
+    self.read_timeout = Duration::from_secs(limits.read_timeout);
+    self.max_tarball_size = limits.max_tarball_size;
+    self
+  }
+
+  fn get(&self, url: String) -> RequestBuilder {
+    let req = self.client.get(url);
+    match self.auth {
+      Some(RegistryAuth::Bearer(ref token)) => req.bearer_auth(token),
+      Some(RegistryAuth::Basic {
+        ref username,
+        ref password,
+      }) => req.basic_auth(username, Some(password)),
+      None => req,
+    }
+  }
+}
+
+#[poem::async_trait]
+impl Registry for HttpRegistry {
+  async fn packument(&self, package_name: &str) -> anyhow::Result<Packument> {
+    let name = encode_package_name(package_name);
+    let info_url = format!("{}/{name}", self.url);
+
+    tracing::debug!(
+      "Fetching package info for {} from {}",
+      package_name,
+      info_url
+    );
+
+    let res = tokio::time::timeout(self.read_timeout, self.get(info_url).send())
+      .await
+      .map_err(|_| AppError::UpstreamTimeout(package_name.to_owned()))?
+      .map_err(|e| map_timeout(e, package_name))?;
+    let code = res.status();
+    if code == StatusCode::OK {
+      let res = res
+        .json::<Packument>()
+        .await
+        .map_err(|e| map_timeout(e, package_name))?;
+      Ok(res)
+    } else if code == StatusCode::NOT_FOUND {
+      Err(AppError::NotFoundPackage(package_name.to_owned()).into())
+    } else {
+      let content = res.text().await?;
+      tracing::error!(
+        "Error fetching info for {} (status: {})",
+        package_name,
+        code
+      );
+      anyhow::bail!(content)
+    }
+  }
+
+  async fn tarball(&self, package_name: &str, version: &str) -> anyhow::Result<Bytes> {
+    let tarball_url = format!(
+      "{}/{package_name}/-/{}",
+      self.url,
+      tarball_filename(package_name, version)
+    );
+
+    tracing::debug!("Fetching package for {package_name} from {tarball_url}");
+
+    let package_spec = format!("{package_name}@{version}");
+    let timed_out = || AppError::UpstreamTimeout(package_spec.clone());
+    let mut resp = tokio::time::timeout(self.read_timeout, self.get(tarball_url).send())
+      .await
+      .map_err(|_| timed_out())?
+      .map_err(|e| map_timeout(e, &package_spec))?;
+    let code = resp.status();
+    if code != StatusCode::OK {
+      tracing::error!(
+        "Error fetching tarball for {}@{} (status: {})",
+        package_name,
+        version,
+        code
+      );
+      return Err(
+        AppError::UnableFetchTarball {
+          package_spec: package_spec.clone(),
+          status: code.as_u16(),
+        }
+        .into(),
+      );
+    }
+
+    // Checked while streaming too, as `Content-Length` may be missing or wrong.
+    let too_large = || AppError::TarballTooLarge {
+      package_spec: package_spec.clone(),
+      limit: self.max_tarball_size,
+    };
+    if resp
+      .content_length()
+      .is_some_and(|len| len > self.max_tarball_size)
+    {
+      return Err(too_large().into());
+    }
+    let mut body = BytesMut::new();
+    while let Some(chunk) = tokio::time::timeout(self.read_timeout, resp.chunk())
+      .await
+      .map_err(|_| timed_out())?
+      .map_err(|e| map_timeout(e, &package_spec))?
+    {
+      if (body.len() + chunk.len()) as u64 > self.max_tarball_size {
+        return Err(too_large().into());
+      }
+      body.extend_from_slice(&chunk);
+    }
+
+    Ok(body.freeze())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+  };
+
+  use super::*;
+
+  /// Answers one request with a body of unknown length, sent after `delay`.
+  async fn serve_once(body: Vec<u8>, delay: Duration) -> anyhow::Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+      let (mut socket, _) = listener.accept().await?;
+      let _request = socket.read(&mut [0; 1024]).await?;
+      socket
+        .write_all(b"HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n")
+        .await?;
+      tokio::time::sleep(delay).await;
+      socket.write_all(&body).await?;
+      anyhow::Ok(())
+    });
+    Ok(format!("http://{addr}"))
+  }
+
+  #[tokio::test]
+  async fn test_tarball_limits() -> anyhow::Result<()> {
+    let limits = LimitsConfig {
+      read_timeout: 1,
+      max_tarball_size: 16,
+      ..Default::default()
+    };
+
+    let url = serve_once(vec![0; 64], Duration::ZERO).await?;
+    let registry = HttpRegistry::new(url).with_limits(&limits);
+    let error = registry.tarball("pkg", "1.0.0").await.unwrap_err();
+    assert!(matches!(
+      AppError::from(error),
+      AppError::TarballTooLarge { limit: 16, .. }
+    ));
+
+    let url = serve_once(vec![0; 8], Duration::from_secs(3)).await?;
+    let registry = HttpRegistry::new(url).with_limits(&limits);
+    let error = registry.tarball("pkg", "1.0.0").await.unwrap_err();
+    assert!(matches!(
+      AppError::from(error),
+      AppError::UpstreamTimeout(_)
+    ));
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_packument() -> anyhow::Result<()> {
+    let packument = serde_json::json!({
+      "name": "builtins",
+      "dist-tags": { "latest": "1.0.0" },
+      "versions": { "1.0.0": { "name": "builtins", "version": "1.0.0" } }
+    });
+    let url = serve_once(serde_json::to_vec(&packument)?, Duration::ZERO).await?;
+    let registry = HttpRegistry::new(url);
+    let packument = registry.packument("builtins").await?;
+    assert_eq!(packument.dist_tags["latest"], "1.0.0");
+    assert!(packument.versions.contains_key("1.0.0"));
+    Ok(())
+  }
+}
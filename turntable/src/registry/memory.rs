@@ -0,0 +1,73 @@
+use std::{collections::HashMap, sync::RwLock};
+
+use bytes::Bytes;
+use serde_json::Value;
+
+use super::{Packument, Registry};
+use crate::{errors::AppError, models::PackageConfig, utils::encrypt::get_intergrity};
+
+#[derive(Debug, Default)]
+struct MemoryPackage {
+  packument: Packument,
+  tarballs: HashMap<String, Bytes>,
+}
+
+/// A registry kept in memory, mostly useful for tests.
+#[derive(Debug, Default)]
+pub struct MemoryRegistry {
+  packages: RwLock<HashMap<String, MemoryPackage>>,
+}
+
+impl MemoryRegistry {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Adds the version described by `package_json` and tags it `latest`.
+  pub fn insert(&self, mut package_json: Value, tarball: Bytes) -> anyhow::Result<()> {
+    let name = package_json["name"]
+      .as_str()
+      .ok_or(anyhow::anyhow!("package.json has no name"))?
+      .to_owned();
+    let version = package_json["version"]
+      .as_str()
+      .ok_or(anyhow::anyhow!("package.json has no version"))?
+      .to_owned();
+    package_json["dist"] = serde_json::json!({ "integrity": get_intergrity(&tarball)? });
+
+    let mut packages = self.packages.write().expect("lock memory registry");
+    let package = packages.entry(name).or_default();
+    package
+      .packument
+      .versions
+      .insert(version.clone(), serde_json::from_value::<PackageConfig>(package_json)?);
+    package
+      .packument
+      .dist_tags
+      .insert("latest".into(), version.clone());
+    package.tarballs.insert(version, tarball);
+    Ok(())
+  }
+}
+
+#[poem::async_trait]
+impl Registry for MemoryRegistry {
+  async fn packument(&self, package_name: &str) -> anyhow::Result<Packument> {
+    let packages = self.packages.read().expect("lock memory registry");
+    match packages.get(package_name) {
+      Some(package) => Ok(package.packument.clone()),
+      None => Err(AppError::NotFoundPackage(package_name.to_owned()).into()),
+    }
+  }
+
+  async fn tarball(&self, package_name: &str, version: &str) -> anyhow::Result<Bytes> {
+    let packages = self.packages.read().expect("lock memory registry");
+    packages
+      .get(package_name)
+      .and_then(|package| package.tarballs.get(version))
+      .cloned()
+      .ok_or(anyhow::anyhow!(
+        "Cannot find tarball for {package_name}@{version}"
+      ))
+  }
+}
@@ -0,0 +1,154 @@
+mod fs;
+mod http;
+mod memory;
+mod scoped;
+
+use std::{collections::HashMap, sync::Arc};
+
+use bytes::Bytes;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use urlencoding::encode;
+
+use crate::config::{Config, LimitsConfig};
+
+pub use self::fs::*;
+pub use self::http::*;
+pub use self::memory::*;
+pub use self::scoped::*;
+pub use crate::models::PackageConfig;
+
+/// The registry document of a package.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct Packument {
+  #[serde(default)]
+  pub versions: HashMap<String, PackageConfig>,
+  #[serde(default)]
+  pub dist_tags: HashMap<String, String>,
+  #[serde(flatten)]
+  pub rest: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct VersionsAndTags {
+  pub versions: Vec<String>,
+  pub tags: HashMap<String, String>,
+}
+
+impl From<Packument> for VersionsAndTags {
+  fn from(packument: Packument) -> Self {
+    Self {
+      versions: packument.versions.into_keys().collect(),
+      tags: packument.dist_tags,
+    }
+  }
+}
+
+/// A source of packuments and tarballs.
+#[poem::async_trait]
+pub trait Registry: Send + Sync {
+  async fn packument(&self, package_name: &str) -> anyhow::Result<Packument>;
+
+  async fn tarball(&self, package_name: &str, version: &str) -> anyhow::Result<Bytes>;
+}
+
+/// Creates the registry behind `url`: `file://` urls point at a local
+/// [`FsRegistry`] directory, anything else is fetched over HTTP within `limits`.
+pub fn from_url(
+  url: &str,
+  auth: Option<RegistryAuth>,
+  limits: &LimitsConfig,
+) -> anyhow::Result<Arc<dyn Registry>> {
+  let parsed = Url::parse(url)?;
+  if parsed.scheme() == "file" {
+    let root = parsed
+      .to_file_path()
+      .map_err(|_| anyhow::anyhow!("invalid registry directory {url}"))?;
+    return Ok(Arc::new(FsRegistry::new(root)));
+  }
+  let registry = HttpRegistry::new(url).with_limits(limits);
+  Ok(Arc::new(match auth {
+    Some(auth) => registry.with_auth(auth),
+    None => registry,
+  }))
+}
+
+/// Creates the default registry of `config` and routes each configured scope
+/// to its own registry.
+pub fn from_config(config: &Config) -> anyhow::Result<Arc<dyn Registry>> {
+  let auth = config
+    .npm_registry_token
+    .as_ref()
+    .map(|token| RegistryAuth::Bearer(token.expose().to_owned()));
+  let default = from_url(&config.npm_registry_url, auth, &config.limits)?;
+  if config.scopes.is_empty() {
+    return Ok(default);
+  }
+
+  let mut registry = ScopedRegistry::new(default);
+  for (scope, scope_config) in config.scopes.iter() {
+    let auth = match (&scope_config.token, &scope_config.username, &scope_config.password) {
+      (Some(token), _, _) => Some(RegistryAuth::Bearer(token.expose().to_owned())),
+      (None, Some(username), Some(password)) => Some(RegistryAuth::Basic {
+        username: username.to_owned(),
+        password: password.expose().to_owned(),
+      }),
+      _ => None,
+    };
+    registry = registry.scope(scope, from_url(&scope_config.url, auth, &config.limits)?);
+  }
+  Ok(Arc::new(registry))
+}
+
+#[inline]
+pub(crate) fn is_scoped_package_name(pkg_name: impl AsRef<str>) -> bool {
+  pkg_name.as_ref().starts_with('@')
+}
+
+#[inline]
+pub(crate) fn encode_package_name(pkg_name: impl AsRef<str>) -> String {
+  let pkg_name: &str = pkg_name.as_ref();
+  if is_scoped_package_name(pkg_name) {
+    format!("@{}", encode(&pkg_name[1..]))
+  } else {
+    encode(pkg_name).to_string()
+  }
+}
+
+/// `@scope/name@1.0.0` is published as `name-1.0.0.tgz`.
+#[inline]
+pub(crate) fn tarball_filename(package_name: &str, version: &str) -> String {
+  let tarball_name = if is_scoped_package_name(package_name) {
+    package_name.split('/').nth(1).unwrap_or_default()
+  } else {
+    package_name
+  };
+  format!("{tarball_name}-{version}.tgz")
+}
+
+#[cfg(test)]
+pub(crate) async fn create_tarball(files: &[(&str, &str)]) -> anyhow::Result<Bytes> {
+  use async_compression::tokio::write::GzipEncoder;
+  use tokio::io::AsyncWriteExt;
+  use tokio_tar::{Builder, Header};
+
+  let mut builder = Builder::new(Vec::new());
+  for (path, content) in files {
+    let mut header = Header::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(1684947507);
+    header.set_cksum();
+    builder
+      .append_data(&mut header, format!("package{path}"), content.as_bytes())
+      .await?;
+  }
+  let tar = builder.into_inner().await?;
+
+  let mut encoder = GzipEncoder::new(Vec::new());
+  encoder.write_all(&tar).await?;
+  encoder.shutdown().await?;
+  Ok(encoder.into_inner().into())
+}
@@ -0,0 +1,76 @@
+use std::{collections::HashMap, sync::Arc};
+
+use bytes::Bytes;
+
+use super::{Packument, Registry};
+
+/// Routes scoped packages to their own registry and everything else to a
+/// default one.
+pub struct ScopedRegistry {
+  default: Arc<dyn Registry>,
+  scopes: HashMap<String, Arc<dyn Registry>>,
+}
+
+impl ScopedRegistry {
+  pub fn new(default: Arc<dyn Registry>) -> Self {
+    Self {
+      default,
+      scopes: HashMap::new(),
+    }
+  }
+
+  /// Serves every package of `scope` (`@ourco`) from `registry`.
+  pub fn scope(mut self, scope: impl Into<String>, registry: Arc<dyn Registry>) -> Self {
+    self.scopes.insert(scope.into(), registry);
+    self
+  }
+
+  fn registry_for(&self, package_name: &str) -> &dyn Registry {
+    package_name
+      .split_once('/')
+      .and_then(|(scope, _)| self.scopes.get(scope))
+      .unwrap_or(&self.default)
+      .as_ref()
+  }
+}
+
+#[poem::async_trait]
+impl Registry for ScopedRegistry {
+  async fn packument(&self, package_name: &str) -> anyhow::Result<Packument> {
+    self.registry_for(package_name).packument(package_name).await
+  }
+
+  async fn tarball(&self, package_name: &str, version: &str) -> anyhow::Result<Bytes> {
+    self
+      .registry_for(package_name)
+      .tarball(package_name, version)
+      .await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::registry::MemoryRegistry;
+
+  #[tokio::test]
+  async fn test_route_by_scope() -> anyhow::Result<()> {
+    let public = MemoryRegistry::new();
+    public.insert(
+      serde_json::json!({ "name": "react", "version": "18.2.0" }),
+      Bytes::from_static(b"public"),
+    )?;
+    let private = MemoryRegistry::new();
+    private.insert(
+      serde_json::json!({ "name": "@ourco/widget", "version": "1.2.0" }),
+      Bytes::from_static(b"private"),
+    )?;
+
+    let registry = ScopedRegistry::new(Arc::new(public)).scope("@ourco", Arc::new(private));
+
+    assert_eq!(registry.tarball("react", "18.2.0").await?, "public");
+    assert_eq!(registry.tarball("@ourco/widget", "1.2.0").await?, "private");
+    assert!(registry.packument("@other/widget").await.is_err());
+    Ok(())
+  }
+}
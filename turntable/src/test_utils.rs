@@ -0,0 +1,50 @@
+use std::sync::Arc;
+
+use poem::{endpoint::BoxEndpoint, test::TestClient};
+use serde_json::Value;
+
+use crate::{
+  config::Config,
+  registry::{create_tarball, MemoryRegistry},
+  Server,
+};
+
+pub(crate) type Client = TestClient<BoxEndpoint<'static>>;
+
+/// Adds the version described by `package_json` with `files` to `registry`.
+pub(crate) async fn insert_package(
+  registry: &MemoryRegistry,
+  package_json: Value,
+  files: &[(&str, &str)],
+) -> anyhow::Result<()> {
+  registry.insert(package_json, create_tarball(files).await?)
+}
+
+/// A client of the whole server with `config`, reading from `registry`.
+pub(crate) fn test_client_with(config: Config, registry: MemoryRegistry) -> anyhow::Result<Client> {
+  let server = Server::with_registry(config, Arc::new(registry))?;
+  Ok(TestClient::new(server.ep))
+}
+
+/// A client of the server with the default config and a registry holding only
+/// the version described by `package_json` with `files`.
+pub(crate) async fn test_client(
+  package_json: Value,
+  files: &[(&str, &str)],
+) -> anyhow::Result<Client> {
+  let registry = MemoryRegistry::new();
+  insert_package(&registry, package_json, files).await?;
+  test_client_with(Config::default(), registry)
+}
+
+/// `pkg@1.0.0` with `main` at `/index.js` and a `/lib` directory.
+pub(crate) async fn pkg_client() -> anyhow::Result<Client> {
+  test_client(
+    serde_json::json!({ "name": "pkg", "version": "1.0.0", "main": "index.js" }),
+    &[
+      ("/index.js", "export default 1;"),
+      ("/lib/index.js", "export default 2;"),
+    ],
+  )
+  .await
+}
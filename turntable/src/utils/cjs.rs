@@ -0,0 +1,337 @@
+use std::fmt::Write;
+
+use crate::models::PackageConfig;
+
+/// Modules of Node.js that have no url to import them from. Requiring one
+/// throws when it runs, like a missing module would.
+const NODE_BUILTINS: &[&str] = &[
+  "assert",
+  "buffer",
+  "child_process",
+  "crypto",
+  "events",
+  "fs",
+  "http",
+  "https",
+  "module",
+  "net",
+  "os",
+  "path",
+  "process",
+  "querystring",
+  "stream",
+  "string_decoder",
+  "tty",
+  "url",
+  "util",
+  "vm",
+  "worker_threads",
+  "zlib",
+];
+
+/// What a CommonJS module exports and requires, found without running it.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CommonJsModule {
+  /// Named exports, like `exports.foo = ..`.
+  pub exports: Vec<String>,
+  /// Modules whose exports are re-exported, like `module.exports =
+  /// require("./lib")`.
+  pub reexports: Vec<String>,
+  /// Every `require("..")` with a string literal, in order.
+  pub requires: Vec<String>,
+}
+
+/// Whether `code` is CommonJS rather than an ES module. `.mjs`, `.cjs` and
+/// `"type": "module"` decide first, then the syntax used.
+pub fn is_commonjs(filename: &str, code: &str, package_config: &PackageConfig) -> bool {
+  if filename.ends_with(".mjs") {
+    return false;
+  }
+  if filename.ends_with(".cjs") {
+    return true;
+  }
+  if package_config.get_str("type") == Some("module")
+    || regex!(r#"(?m)^\s*(?:import\b\s*[\w{*"']|export\b\s*[\w{*])"#).is_match(code)
+  {
+    return false;
+  }
+  regex!(r"\brequire\s*\(|\bmodule\.exports\b|\bexports\.[\w$]+\s*=").is_match(code)
+}
+
+#[inline]
+fn push_unique(list: &mut Vec<String>, value: &str) {
+  if !list.iter().any(|item| item == value) {
+    list.push(value.to_owned());
+  }
+}
+
+/// The keys of the object literal starting right after its `{`, for
+/// `module.exports = { a, b: .., c() {} }`.
+fn object_literal_keys(code: &str) -> Vec<&str> {
+  let key = regex!(r#"^(?:["']([\w$]+)["']|([A-Za-z_$][\w$]*))\s*(?::|\(|$)"#);
+  let mut keys = Vec::new();
+  let (mut depth, mut quote, mut start) = (0, None, 0);
+  let mut chars = code.char_indices();
+  while let Some((i, c)) = chars.next() {
+    match (quote, c) {
+      (Some(_), '\\') => {
+        chars.next();
+      }
+      (Some(q), c) if c == q => quote = None,
+      (Some(_), _) => {}
+      (None, '"' | '\'' | '`') => quote = Some(c),
+      (None, '(' | '[' | '{') => depth += 1,
+      (None, ',' | '}') if depth == 0 => {
+        let property = code[start..i].trim();
+        if let Some(key) = key
+          .captures(property)
+          .and_then(|captures| captures.get(1).or(captures.get(2)))
+        {
+          keys.push(key.as_str());
+        }
+        if c == '}' {
+          break;
+        }
+        start = i + 1;
+      }
+      (None, ')' | ']' | '}') => depth -= 1,
+      _ => {}
+    }
+  }
+  keys
+}
+
+/// Finds the exports and requires of a CommonJS module with the patterns
+/// that compilers and hand-written modules use.
+pub fn analyze_commonjs(code: &str) -> CommonJsModule {
+  let mut module = CommonJsModule::default();
+  let exports = [
+    regex!(r"(?:\bmodule\.|[^.\w$]|^)exports\.([\w$]+)\s*="),
+    regex!(r#"(?:\bmodule\.|[^.\w$]|^)exports\[\s*["']([\w$]+)["']\s*\]\s*="#),
+    regex!(r#"Object\.defineProperty\(\s*(?:module\.)?exports\s*,\s*["']([\w$]+)["']"#),
+  ];
+  for re in exports {
+    for captures in re.captures_iter(code) {
+      // `exports.a == ..` compares instead of assigning.
+      if !code[captures.get(0).unwrap().end()..].starts_with('=') {
+        push_unique(&mut module.exports, &captures[1]);
+      }
+    }
+  }
+  for m in regex!(r"\bmodule\.exports\s*=\s*\{").find_iter(code) {
+    for key in object_literal_keys(&code[m.end()..]) {
+      push_unique(&mut module.exports, key);
+    }
+  }
+  module.exports.retain(|name| {
+    name != "default" && name != "__esModule" && regex!(r"^[A-Za-z_$][\w$]*$").is_match(name)
+  });
+
+  let reexports = [
+    regex!(r#"\bmodule\.exports\s*=\s*require\(\s*["']([^"']+)["']\s*\)"#),
+    regex!(r#"__exportStar\(\s*require\(\s*["']([^"']+)["']\s*\)"#),
+    regex!(r#"__export\(\s*require\(\s*["']([^"']+)["']\s*\)\s*\)"#),
+  ];
+  for re in reexports {
+    for captures in re.captures_iter(code) {
+      push_unique(&mut module.reexports, &captures[1]);
+    }
+  }
+  for captures in regex!(r#"\brequire\(\s*(?:"([^"]+)"|'([^']+)')\s*\)"#).captures_iter(code) {
+    let specifier = captures.get(1).or(captures.get(2)).unwrap().as_str();
+    let builtin = specifier.starts_with("node:")
+      || NODE_BUILTINS.contains(&specifier.split('/').next().unwrap_or_default());
+    if !builtin {
+      push_unique(&mut module.requires, specifier);
+    }
+  }
+  module
+}
+
+/// Resolves a relative `specifier` against the file requiring it, both
+/// absolute in the package.
+pub fn resolve_relative(filename: &str, specifier: &str) -> Option<String> {
+  if !specifier.starts_with("./") && !specifier.starts_with("../") {
+    return None;
+  }
+  let mut segments = filename.split('/').collect::<Vec<_>>();
+  segments.pop();
+  for segment in specifier.split('/') {
+    match segment {
+      "." | "" => {}
+      ".." => {
+        segments.pop().filter(|segment| !segment.is_empty())?;
+      }
+      segment => segments.push(segment),
+    }
+  }
+  Some(segments.join("/"))
+}
+
+/// An ES module running `code` with `module`, `exports` and `require`, whose
+/// default export is `module.exports` and named exports are `exports`.
+/// Required modules are imported up front, so the result still needs its
+/// specifiers rewritten.
+pub fn wrap_commonjs(
+  code: &str,
+  filename: &str,
+  requires: &[String],
+  exports: &[String],
+) -> String {
+  let quote = |value: &str| serde_json::Value::from(value).to_string();
+  let mut module = String::new();
+  for (i, specifier) in requires.iter().enumerate() {
+    let _ = writeln!(
+      module,
+      "import * as __cjs_import{i} from {};",
+      quote(specifier)
+    );
+  }
+  module.push_str(concat!(
+    "const __cjs_interop = (ns) => ns.__cjsModule || (Object.keys(ns).length === 1 && \"default\" in ns)",
+    " ? ns.default : { __esModule: true, ...ns };\n",
+    "const __cjs_modules = {",
+  ));
+  for (i, specifier) in requires.iter().enumerate() {
+    let _ = write!(
+      module,
+      " {}: __cjs_interop(__cjs_import{i}),",
+      quote(specifier)
+    );
+  }
+  let dirname = match filename.rsplit_once('/') {
+    Some(("", _)) | None => "/",
+    Some((dirname, _)) => dirname,
+  };
+  let _ = write!(
+    module,
+    r#" }};
+const require = (id) => {{
+  if (id in __cjs_modules) return __cjs_modules[id];
+  throw new Error(`Cannot find module "${{id}}"`);
+}};
+const process = globalThis.process || {{ env: {{ NODE_ENV: "production" }} }};
+const module = {{ exports: {{}} }};
+(function (exports, require, module, __filename, __dirname, global) {{
+{code}
+}}).call(module.exports, module.exports, require, module, {}, {}, globalThis);
+const __cjs_exports = module.exports;
+export default __cjs_exports;
+export const __cjsModule = true;
+"#,
+    quote(filename),
+    quote(dirname)
+  );
+  for (i, name) in exports.iter().enumerate() {
+    let _ = writeln!(
+      module,
+      "const __cjs_export{i} = __cjs_exports[{}];",
+      quote(name)
+    );
+  }
+  if !exports.is_empty() {
+    let names = exports
+      .iter()
+      .enumerate()
+      .map(|(i, name)| format!("__cjs_export{i} as {name}"))
+      .collect::<Vec<_>>();
+    let _ = writeln!(module, "export {{ {} }};", names.join(", "));
+  }
+  module
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_is_commonjs() {
+    let config = PackageConfig::default();
+    assert!(is_commonjs("/index.js", "module.exports = 1;", &config));
+    assert!(is_commonjs("/index.cjs", "", &config));
+    assert!(!is_commonjs("/index.js", "export default 1;", &config));
+    assert!(!is_commonjs("/index.mjs", "module.exports = 1;", &config));
+    assert!(!is_commonjs("/index.js", "const a = 1;", &config));
+    assert!(!is_commonjs("/index.js", "export{a};", &config));
+    assert!(is_commonjs("/index.js", "exports.a = 1;", &config));
+    assert!(is_commonjs(
+      "/index.js",
+      "exports.a = exports.b = void 0;\nexports.a = require(\"./a\");",
+      &config
+    ));
+    assert!(is_commonjs(
+      "/index.js",
+      "importScripts(\"a.js\");\nimports.x = require(\"x\");",
+      &config
+    ));
+  }
+
+  #[test]
+  fn test_analyze_commonjs() {
+    let module = analyze_commonjs(
+      r#"
+        "use strict";
+        Object.defineProperty(exports, "__esModule", { value: true });
+        exports.a = exports.b = void 0;
+        exports["c"] = 3;
+        Object.defineProperty(exports, "d", { get: () => d });
+        __exportStar(require("./lib"), exports);
+        const path = require("path");
+        const dep = require('dep/sub');
+        if (exports.a === 1) {}
+        module.exports = { e, f: require("./f"), g() { return [1, 2]; }, "h": 4 };
+      "#,
+    );
+    assert_eq!(module.exports, ["a", "b", "c", "d", "e", "f", "g", "h"]);
+    assert_eq!(module.reexports, ["./lib"]);
+    assert_eq!(module.requires, ["./lib", "dep/sub", "./f"]);
+
+    let module = analyze_commonjs(
+      r#"
+        if (process.env.NODE_ENV === 'production') {
+          module.exports = require('./cjs/react.production.min.js');
+        } else {
+          module.exports = require('./cjs/react.development.js');
+        }
+      "#,
+    );
+    assert!(module.exports.is_empty());
+    assert_eq!(
+      module.reexports,
+      [
+        "./cjs/react.production.min.js",
+        "./cjs/react.development.js"
+      ]
+    );
+  }
+
+  #[test]
+  fn test_resolve_relative() {
+    assert_eq!(
+      resolve_relative("/lib/index.js", "./a.js").as_deref(),
+      Some("/lib/a.js")
+    );
+    assert_eq!(
+      resolve_relative("/lib/index.js", "../cjs/b").as_deref(),
+      Some("/cjs/b")
+    );
+    assert_eq!(resolve_relative("/index.js", "../a"), None);
+    assert_eq!(resolve_relative("/index.js", "dep"), None);
+  }
+
+  #[test]
+  fn test_wrap_commonjs() {
+    let module = wrap_commonjs(
+      "exports.a = require(\"dep\");",
+      "/lib/index.js",
+      &["dep".to_owned()],
+      &["a".to_owned()],
+    );
+    assert!(module.starts_with("import * as __cjs_import0 from \"dep\";\n"));
+    assert!(module.contains("const __cjs_modules = { \"dep\": __cjs_interop(__cjs_import0), };"));
+    assert!(module.contains(
+      "}).call(module.exports, module.exports, require, module, \"/lib/index.js\", \"/lib\", globalThis);"
+    ));
+    assert!(module.ends_with("export { __cjs_export0 as a };\n"));
+  }
+}
@@ -0,0 +1,120 @@
+use regex::Captures;
+use reqwest::Url;
+
+use crate::models::PackageConfig;
+
+/// A `~pkg/file` reference, or a bare one like `normalize.css` whose package
+/// is a dependency. Other bare references are relative in CSS.
+fn package_reference<'a>(
+  value: &'a str,
+  dependencies: &serde_json::Value,
+) -> Option<(&'a str, &'a str)> {
+  let (value, explicit) = match value.strip_prefix('~') {
+    Some(value) => (value, true),
+    None => (value, false),
+  };
+  if value.starts_with(['.', '/', '#']) || value.contains(':') {
+    return None;
+  }
+  let captures = regex!(r"^((?:@[^/]+/)?[^/]+)(/.*)?$").captures(value)?;
+  let package_name = captures.get(1)?.as_str();
+  if !explicit && dependencies.get(package_name).is_none() {
+    return None;
+  }
+  let file = captures.get(2).map_or("", |file| file.as_str());
+  Some((package_name, file))
+}
+
+/// Rewrites the package references of `@import` and `url()` to urls under
+/// `origin`, at the versions of `dependencies`. Imported stylesheets get
+/// `?css` too, so their own references are rewritten when they load.
+/// Relative references resolve against the stylesheet's url, or against
+/// `base_url` when it is given, for sheets that are not loaded from their url.
+pub fn rewrite_css(
+  css: &str,
+  origin: &str,
+  package_config: &PackageConfig,
+  base_url: Option<&Url>,
+) -> String {
+  let dependencies = package_config.dependencies();
+  let rewrite = |value: &str, import: bool| -> Option<String> {
+    let query = match (import, value.contains('?')) {
+      (false, _) => "",
+      (true, false) => "?css",
+      (true, true) => "&css",
+    };
+    match package_reference(value, &dependencies) {
+      Some((package_name, file)) => {
+        let version = dependencies
+          .get(package_name)
+          .and_then(|version| version.as_str())
+          .unwrap_or("latest");
+        Some(format!("{origin}/{package_name}@{version}{file}{query}"))
+      }
+      None if value.contains(':') || value.starts_with(['#', '?']) => None,
+      None => match base_url {
+        Some(base_url) => base_url.join(value).ok().map(|url| format!("{url}{query}")),
+        None if import && !value.starts_with("//") => Some(format!("{value}{query}")),
+        None => None,
+      },
+    }
+  };
+
+  regex!(
+    r#"(?s)/\*.*?\*/|@import\s+(?:"([^"]*)"|'([^']*)'|url\(\s*(?:"([^"]*)"|'([^']*)'|([^)"'\s]*))\s*\))|url\(\s*(?:"([^"]*)"|'([^']*)'|([^)"'\s]*))\s*\)"#
+  )
+  .replace_all(css, |captures: &Captures| {
+    let (value, import) = match (1..=8).find_map(|i| Some((captures.get(i)?, i <= 5))) {
+      Some((value, import)) => (value.as_str(), import),
+      None => return captures[0].to_owned(),
+    };
+    match rewrite(value, import) {
+      Some(url) if import => format!(r#"@import "{}""#, url.replace('"', "%22")),
+      Some(url) => format!(r#"url("{}")"#, url.replace('"', "%22")),
+      None => captures[0].to_owned(),
+    }
+  })
+  .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_rewrite_css() -> anyhow::Result<()> {
+    let package_config: PackageConfig = serde_json::from_value(serde_json::json!({
+      "dependencies": { "normalize.css": "^8.0.1" },
+      "peerDependencies": { "@fontsource/inter": "5.0.0" }
+    }))?;
+    let css = r#"@import "normalize.css";
+@import url('./base.css') screen;
+@import "https://fonts.example.com/a.css";
+/* @import "normalize.css"; */
+@font-face { src: url(~@fontsource/inter/files/inter.woff2) format("woff2"); }
+.logo { background: url("logo.svg"), url(data:image/png;base64,AAAA); }
+"#;
+    assert_eq!(
+      rewrite_css(css, "https://unpkg.com", &package_config, None),
+      r#"@import "https://unpkg.com/normalize.css@^8.0.1?css";
+@import "./base.css?css" screen;
+@import "https://fonts.example.com/a.css";
+/* @import "normalize.css"; */
+@font-face { src: url("https://unpkg.com/@fontsource/inter@5.0.0/files/inter.woff2") format("woff2"); }
+.logo { background: url("logo.svg"), url(data:image/png;base64,AAAA); }
+"#
+    );
+
+    let base_url = Url::parse("https://unpkg.com/theme@1.0.0/dist/theme.css")?;
+    assert_eq!(
+      rewrite_css(
+        r#".logo { background: url("../logo.svg") }"#,
+        "https://unpkg.com",
+        &package_config,
+        Some(&base_url)
+      ),
+      r#".logo { background: url("https://unpkg.com/theme@1.0.0/logo.svg") }"#
+    );
+    Ok(())
+  }
+}
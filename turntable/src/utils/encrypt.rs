@@ -1,5 +1,8 @@
 use base64::Engine;
-use hashes::{sha1, sha2::sha384};
+use hashes::{
+  sha1,
+  sha2::{sha256, sha384, sha512},
+};
 
 pub use base64::engine::general_purpose::STANDARD as base64;
 
@@ -10,6 +13,57 @@ pub fn get_intergrity(content: impl AsRef<[u8]>) -> anyhow::Result<String> {
   Ok(format!("sha384-{}", base64.encode(digest.into_bytes())))
 }
 
+/// The algorithms of integrity strings, weakest first.
+const ALGORITHMS: [&str; 4] = ["sha1", "sha256", "sha384", "sha512"];
+
+/// Checks `content` against a subresource integrity string like `sha512-<base64>`.
+/// Only the hashes of the strongest algorithm listed count, so a weak `sha1`
+/// next to a `sha512` cannot vouch for the content on its own.
+pub fn verify_integrity(content: impl AsRef<[u8]>, integrity: impl AsRef<str>) -> bool {
+  let hashes = integrity
+    .as_ref()
+    .split_whitespace()
+    .filter_map(|hash| {
+      let (algorithm, expected) = hash.split_once('-')?;
+      let strength = ALGORITHMS.iter().position(|a| *a == algorithm)?;
+      Some((strength, algorithm, expected))
+    })
+    .collect::<Vec<_>>();
+  let Some(strongest) = hashes.iter().map(|(strength, ..)| *strength).max() else {
+    return false;
+  };
+  hashes
+    .iter()
+    .filter(|(strength, ..)| *strength == strongest)
+    .any(|(_, algorithm, expected)| digest(content.as_ref(), algorithm) == *expected)
+}
+
+fn digest(content: &[u8], algorithm: &str) -> String {
+  match algorithm {
+    "sha1" => base64.encode(sha1::hash(content).into_bytes()),
+    "sha256" => base64.encode(sha256::hash(content).into_bytes()),
+    "sha384" => base64.encode(sha384::hash(content).into_bytes()),
+    _ => base64.encode(sha512::hash(content).into_bytes()),
+  }
+}
+
+/// Converts a hex encoded `dist.shasum` into an equivalent `sha1-` integrity string.
+pub fn shasum_to_integrity(shasum: impl AsRef<str>) -> Option<String> {
+  let shasum = shasum.as_ref();
+  // Checked first, so slicing by bytes never cuts a character.
+  if shasum.len() != 40 || !shasum.bytes().all(|b| b.is_ascii_hexdigit()) {
+    return None;
+  }
+
+  let bytes = (0..shasum.len())
+    .step_by(2)
+    .map(|i| u8::from_str_radix(&shasum[i..i + 2], 16).ok())
+    .collect::<Option<Vec<u8>>>()?;
+  Some(format!("sha1-{}", base64.encode(bytes)))
+}
+
+/// The weak ETag of every response: the length and sha1 of its body. Package
+/// files keep theirs in the package index, so it is hashed once per file.
 #[inline]
 pub fn etag(content: impl AsRef<[u8]>) -> anyhow::Result<String> {
   let content = content.as_ref();
@@ -18,3 +72,30 @@ pub fn etag(content: impl AsRef<[u8]>) -> anyhow::Result<String> {
   let hash = &base64.encode(digest.into_bytes())[..27];
   Ok(format!("W/\"{len}-{hash}\""))
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_verify_integrity() {
+    let integrity = get_intergrity("turntable").unwrap();
+    assert!(verify_integrity("turntable", &integrity));
+    assert!(!verify_integrity("turntables", &integrity));
+    assert!(!verify_integrity("turntable", "md5-abc"));
+
+    let sha1 = shasum_to_integrity("32a81eaef65ec7dea920ad6c8cdaaf97151699dd").unwrap();
+    let sha512 = format!("sha512-{}", digest(b"turntable", "sha512"));
+    assert!(verify_integrity("turntable", format!("{sha512} {sha1}")));
+    assert!(verify_integrity("turntable", format!("md5-abc {sha1}")));
+    assert!(!verify_integrity("turntable", format!("sha512-abc {sha1}")));
+  }
+
+  #[test]
+  fn test_shasum_to_integrity() {
+    let integrity = shasum_to_integrity("32a81eaef65ec7dea920ad6c8cdaaf97151699dd").unwrap();
+    assert!(verify_integrity("turntable", integrity));
+    assert_eq!(shasum_to_integrity("xyz"), None);
+    assert_eq!(shasum_to_integrity(format!("{}é0", "0".repeat(37))), None);
+  }
+}
@@ -1,6 +1,8 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use mime_guess::Mime;
+use tempfile::NamedTempFile;
+use tokio::{fs, io::AsyncWriteExt};
 
 pub fn get_content_type(file: &PathBuf) -> Mime {
   let text_files = regex!(r"(?i)/?(\.[a-z]*rc|\.git[a-z]*|\.[a-z]*ignore|\.lock)$");
@@ -10,8 +12,70 @@ pub fn get_content_type(file: &PathBuf) -> Mime {
     .expect("get file name");
 
   if text_files.is_match(name) {
-    mime_guess::mime::TEXT_PLAIN
-  } else {
-    mime_guess::from_path(file).first_or(mime_guess::mime::TEXT_PLAIN)
+    return mime_guess::mime::TEXT_PLAIN;
+  }
+  // Sources `?module` compiles, which `mime_guess` takes for video or plain
+  // JavaScript.
+  let source_type = match file.extension().and_then(|ext| ext.to_str()) {
+    Some("ts" | "mts" | "cts") => Some("application/typescript"),
+    Some("tsx") => Some("text/tsx"),
+    Some("jsx") => Some("text/jsx"),
+    _ => None,
+  };
+  if let Some(ty) = source_type.and_then(|ty| ty.parse().ok()) {
+    return ty;
+  }
+  match mime_guess::from_path(file).first_or(mime_guess::mime::TEXT_PLAIN) {
+    // Newer `mime_guess` prefers `text/javascript`, the rest of turntable
+    // expects one type for JavaScript.
+    ty if ty == mime_guess::mime::TEXT_JAVASCRIPT => mime_guess::mime::APPLICATION_JAVASCRIPT,
+    ty => ty,
+  }
+}
+
+/// Writes through a temporary file so readers never see a partial file. Each
+/// write has its own temporary file, so concurrent writers of one path do not
+/// clobber each other before the rename.
+pub async fn write_atomic(path: &Path, content: &[u8]) -> anyhow::Result<()> {
+  let parent = path
+    .parent()
+    .filter(|parent| !parent.as_os_str().is_empty())
+    .unwrap_or(Path::new("."));
+  fs::create_dir_all(parent).await?;
+  // The temporary file is removed when dropped before `persist`.
+  let (file, tmp) = NamedTempFile::new_in(parent)?.into_parts();
+  let mut file = fs::File::from_std(file);
+  file.write_all(content).await?;
+  file.flush().await?;
+  drop(file);
+  tmp.persist(path)?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use tokio::task::JoinSet;
+
+  use super::*;
+
+  #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+  async fn test_concurrent_write_atomic() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("file");
+
+    let mut writes = JoinSet::new();
+    for i in 0..8u8 {
+      let path = path.clone();
+      writes.spawn(async move { write_atomic(&path, &vec![i; 64 * 1024]).await });
+    }
+    while let Some(result) = writes.join_next().await {
+      result??;
+    }
+
+    let written = std::fs::read(&path)?;
+    assert_eq!(written.len(), 64 * 1024);
+    assert!(written.iter().all(|b| *b == written[0]));
+    assert_eq!(std::fs::read_dir(dir.path())?.count(), 1);
+    Ok(())
   }
 }
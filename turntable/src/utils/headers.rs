@@ -0,0 +1,38 @@
+use poem::{
+  http::{header, StatusCode},
+  IntoResponse, Request, Response,
+};
+
+use crate::models::Entry;
+
+/// `If-Range` has to repeat a strong validator exactly (RFC 9110 §13.1.5).
+/// ETags are weak, so a repeated ETag never matches and gets the whole file,
+/// while `Last-Modified` can, as package files never change.
+#[inline]
+pub fn if_range_matches(req: &Request, last_modified: &str) -> bool {
+  match req
+    .headers()
+    .get(header::IF_RANGE)
+    .and_then(|v| v.to_str().ok())
+  {
+    Some(validator) => validator == last_modified,
+    None => true,
+  }
+}
+
+/// The headers of a file response that the package index alone provides,
+/// shared by the full, partial and `304 Not Modified` responses.
+pub fn file_headers(entry: &Entry) -> Response {
+  let mut tags = vec!["file"];
+  if let Some(ext) = entry.path.extension().and_then(|s| s.to_str()) {
+    tags.push(ext);
+  }
+
+  StatusCode::OK
+    .with_header(header::ACCEPT_RANGES, "bytes")
+    .with_header(header::CACHE_CONTROL, "public, max-age=31536000")
+    .with_header(header::LAST_MODIFIED, &entry.last_modified)
+    .with_header(header::ETAG, &entry.etag)
+    .with_header("Cache-Tag", tags.join(", "))
+    .into_response()
+}
@@ -0,0 +1,152 @@
+use std::fmt::Write;
+
+use regex::Captures;
+
+use super::{
+  escape_html,
+  swc::{rewrite_javascript_esmodule, rewrite_module_specifier},
+};
+use crate::models::PackageConfig;
+
+/// The value of an attribute, quoted or not, without its quotes.
+#[inline]
+fn attribute_value<'a>(captures: &Captures<'a>) -> Option<&'a str> {
+  (2..=4)
+    .find_map(|i| captures.get(i))
+    .map(|value| value.as_str())
+}
+
+/// Calls `f` with the name and value of every attribute of a start tag, and
+/// writes back what it returns, or the attribute as it was.
+fn map_attributes(attrs: &str, mut f: impl FnMut(&str, Option<&str>) -> Option<String>) -> String {
+  regex!(r#"(?s)([^\s"'>/=]+)(?:\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'=<>`]+)))?"#)
+    .replace_all(attrs, |captures: &Captures| {
+      f(&captures[1], attribute_value(captures)).unwrap_or_else(|| captures[0].to_owned())
+    })
+    .into_owned()
+}
+
+#[inline]
+fn is_module_script(attrs: &str) -> bool {
+  let mut module = false;
+  map_attributes(attrs, |name, value| {
+    if name.eq_ignore_ascii_case("type") {
+      module = value.is_some_and(|value| value.trim().eq_ignore_ascii_case("module"));
+    }
+    None
+  });
+  module
+}
+
+/// Rewrites every `<script type="module">` of an HTML page: the `src` gets the
+/// same bare specifier rewriting as an import, and inline code goes through
+/// `rewrite_javascript_esmodule`. Other scripts are left as they are.
+///
+/// Scripts are found with a regex, not an HTML parser. It skips comments,
+/// takes attributes quoted either way or unquoted, and allows `>` in quoted
+/// values. Like a browser it ends a script at the first `</script`, even in a
+/// JavaScript string, so inline code has to write `<\/script>` there. It does
+/// not know the other raw text elements, so a `<script>` written inside
+/// `<textarea>`, `<title>` or `<style>` is rewritten too.
+pub fn rewrite_html_module(
+  html: &str,
+  origin: &str,
+  package_config: &PackageConfig,
+) -> anyhow::Result<String> {
+  let mut result = String::with_capacity(html.len());
+  let mut last = 0;
+  for captures in
+    regex!(r#"(?is)<!--.*?(?:-->|\z)|<script\b((?:[^>"']|"[^"]*"|'[^']*')*)>(.*?)</script\s*>"#)
+      .captures_iter(html)
+  {
+    let (Some(attrs), Some(code)) = (captures.get(1), captures.get(2)) else {
+      continue;
+    };
+    let (script, attrs, code) = (captures.get(0).unwrap(), attrs.as_str(), code.as_str());
+    if !is_module_script(attrs) {
+      continue;
+    }
+
+    let attrs = map_attributes(attrs, |name, value| {
+      let src = value.filter(|_| name.eq_ignore_ascii_case("src"))?;
+      let src = rewrite_module_specifier(&src.replace("&amp;", "&"), origin, package_config);
+      Some(format!(r#"{name}="{}""#, escape_html(&src)))
+    });
+    let code = match code.trim().is_empty() {
+      true => code.to_owned(),
+      false => rewrite_javascript_esmodule(code.to_owned(), origin, package_config)?
+        .replace("</script", "<\\/script"),
+    };
+
+    result.push_str(&html[last..script.start()]);
+    let _ = write!(result, "<script{attrs}>{code}</script>");
+    last = script.end();
+  }
+  result.push_str(&html[last..]);
+  Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_rewrite_html_module() -> anyhow::Result<()> {
+    let package_config: PackageConfig = serde_json::from_value(serde_json::json!({
+      "dependencies": { "dep": "^1.0.0" }
+    }))?;
+    let html = r#"<!DOCTYPE html>
+<script src="dep/index.js"></script>
+<script type="module" src="dep/index.js" async></script>
+<script type=module src='./app.js'></script>
+<SCRIPT TYPE="Module">import dep from "dep";</SCRIPT>
+"#;
+    let result = rewrite_html_module(html, "https://unpkg.com", &package_config)?;
+
+    assert!(result.contains(r#"<script src="dep/index.js"></script>"#));
+    assert!(result.contains(
+      r#"<script type="module" src="https://unpkg.com/dep@^1.0.0/index.js?module" async></script>"#
+    ));
+    assert!(result.contains(r#"<script type=module src="./app.js?module"></script>"#));
+    assert!(result.contains(r#"<script TYPE="Module">"#));
+    assert!(result.contains(r#"from "https://unpkg.com/dep@^1.0.0?module""#));
+    Ok(())
+  }
+
+  #[test]
+  fn test_rewrite_html_module_edge_cases() -> anyhow::Result<()> {
+    let package_config: PackageConfig = serde_json::from_value(serde_json::json!({
+      "dependencies": { "dep": "^1.0.0" }
+    }))?;
+    let rewrite = |html: &str| rewrite_html_module(html, "https://unpkg.com", &package_config);
+
+    // Commented out scripts stay as they are, closed or not.
+    let html = r#"<!-- <script type="module" src="dep"></script> -->"#;
+    assert_eq!(rewrite(html)?, html);
+    let html = r#"<!-- <script type="module" src="dep"></script>"#;
+    assert_eq!(rewrite(html)?, html);
+
+    // Single quoted and unquoted attributes, with `>` in a quoted value.
+    assert_eq!(
+      rewrite(r#"<script type='module' data-x="a>b" src='dep'></script>"#)?,
+      r#"<script type='module' data-x="a>b" src="https://unpkg.com/dep@^1.0.0?module"></script>"#
+    );
+    assert_eq!(
+      rewrite("<script type=module src=dep></script>")?,
+      r#"<script type=module src="https://unpkg.com/dep@^1.0.0?module"></script>"#
+    );
+    assert_eq!(
+      rewrite("<script type=text/javascript src=dep></script>")?,
+      "<script type=text/javascript src=dep></script>"
+    );
+
+    // `</script>` in a string is escaped by the page, and stays escaped.
+    let result =
+      rewrite(r#"<script type="module">import dep from "dep"; dep("<\/script>");</script>"#)?;
+    assert!(result.contains(r#"from "https://unpkg.com/dep@^1.0.0?module""#));
+    assert!(result.contains(r#"<\/script>"#));
+    assert_eq!(result.matches("</script>").count(), 1);
+    assert!(result.ends_with("</script>"));
+    Ok(())
+  }
+}
@@ -1,24 +1,58 @@
+pub mod cjs;
+pub mod css;
 pub mod encrypt;
 pub mod fs;
+pub mod headers;
+pub mod html;
 pub mod npm;
+pub mod range;
+pub mod single_flight;
 pub mod swc;
 pub mod url;
 
-use async_compression::tokio::bufread::GzipDecoder;
-
 use mime_guess::mime;
 use poem::{
   http::{header, StatusCode},
-  IntoResponse,
+  IntoResponse, Response,
 };
-use tokio::io::AsyncReadExt;
-use tokio_tar::{Archive, Entry};
+use serde::Serialize;
+
+use self::encrypt::etag;
 
 #[inline]
 pub fn redirect(path: impl AsRef<str>) -> impl IntoResponse {
   StatusCode::FOUND.with_header(header::LOCATION, path.as_ref())
 }
 
+/// A JSON response with the `etag` of its body, so clients can revalidate it.
+pub fn json_response(value: &impl Serialize) -> anyhow::Result<Response> {
+  let body = serde_json::to_vec(value)?;
+  Ok(
+    StatusCode::OK
+      .with_header(header::CONTENT_TYPE, "application/json; charset=utf-8")
+      .with_header(header::ETAG, etag(&body)?)
+      .with_body(body)
+      .into_response(),
+  )
+}
+
+/// Escapes text for HTML content and attribute values.
+#[inline]
+pub fn escape_html(value: &str) -> String {
+  let mut escaped = String::with_capacity(value.len());
+  for c in value.chars() {
+    match c {
+      '&' => escaped.push_str("&amp;"),
+      '<' => escaped.push_str("&lt;"),
+      '>' => escaped.push_str("&gt;"),
+      '"' => escaped.push_str("&quot;"),
+      '\'' => escaped.push_str("&#39;"),
+      c => escaped.push(c),
+    }
+  }
+  escaped
+}
+
 pub fn get_content_type_header(ty: impl AsRef<str>) -> String {
   let ty = ty.as_ref();
   if ty == mime::APPLICATION_JAVASCRIPT {
@@ -29,18 +63,12 @@ pub fn get_content_type_header(ty: impl AsRef<str>) -> String {
 }
 
 #[inline]
-pub async fn read_entry_file(
-  file: &mut Entry<Archive<GzipDecoder<&[u8]>>>,
-) -> anyhow::Result<Vec<u8>> {
-  let mut content = Vec::new();
-  file.read_to_end(&mut content).await?;
-  Ok(content)
-}
-
-#[inline]
-pub fn strip_suffix_filename(filename: &String) -> &str {
+pub fn strip_suffix_filename(filename: &str) -> &str {
   if filename.ends_with('/') {
-    filename.strip_suffix('/').unwrap_or("/")
+    filename
+      .strip_suffix('/')
+      .filter(|filename| !filename.is_empty())
+      .unwrap_or("/")
   } else {
     filename
   }
@@ -1,175 +1,619 @@
-use std::{collections::HashMap, time::Duration};
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
 
 use bytes::Bytes;
-use cached::proc_macro::cached;
+use cached::{Cached, SizedCache, TimedSizedCache};
+use poem::{FromRequest, Request, RequestBody};
 
-use once_cell::sync::Lazy;
+use crate::{
+  cache::{PackumentCache, TarballCache},
+  config::Config,
+  errors::AppError,
+  models::{PackageConfig, PackageIndex},
+  registry::{FsRegistry, Packument, Registry, VersionsAndTags},
+  utils::{encrypt::verify_integrity, single_flight::SingleFlight},
+};
 
-use reqwest::StatusCode;
-use serde::Deserialize;
+/// Fetches from the registry, saving packuments for offline use. When offline
+/// it only reads saved packuments and never calls the registry.
+///
+/// Packages published to this server are always read from `published`.
+struct Upstream {
+  registry: Arc<dyn Registry>,
+  published: Option<FsRegistry>,
+  packuments: PackumentCache,
+  offline: bool,
+}
 
-use urlencoding::encode;
+impl Upstream {
+  async fn published(&self, package_name: &str) -> Option<&FsRegistry> {
+    let published = self.published.as_ref()?;
+    published.contains(package_name).await.then_some(published)
+  }
 
-use crate::models::{Entry, PackageConfig};
+  async fn packument(&self, package_name: &str) -> Result<Packument, AppError> {
+    if let Some(published) = self.published(package_name).await {
+      return Ok(published.packument(package_name).await?);
+    }
+    if self.offline {
+      return self
+        .packuments
+        .get(package_name)
+        .await
+        .ok_or_else(|| AppError::NotAvailableOffline(package_name.to_owned()));
+    }
 
-static REQUEST_CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
-  reqwest::ClientBuilder::new()
-    .tcp_keepalive(Duration::from_secs(1))
-    .build()
-    .expect("init reqwest client ok!")
-});
+    let packument = self.registry.packument(package_name).await?;
+    if let Err(e) = self.packuments.put(package_name, &packument).await {
+      tracing::warn!("Error caching packument for {package_name}: {e}");
+    }
+    Ok(packument)
+  }
 
-static NPM_REGISTRY_URL: Lazy<&'static str> =
-  Lazy::new(|| option_env!("NPM_REGISTRY_URL").unwrap_or("https:/registry.npmmirror.com"));
+  async fn tarball(&self, package_name: &str, version: &str) -> Result<Bytes, AppError> {
+    if let Some(published) = self.published(package_name).await {
+      return Ok(published.tarball(package_name, version).await?);
+    }
+    if self.offline {
+      return Err(AppError::NotAvailableOffline(format!(
+        "{package_name}@{version}"
+      )));
+    }
+    Ok(self.registry.tarball(package_name, version).await?)
+  }
+}
 
-#[inline]
-fn is_scoped_package_name(pkg_name: impl AsRef<str>) -> bool {
-  pkg_name.as_ref().starts_with('@')
+/// Parsed packages kept in memory. Each holds its whole unpacked archive, so
+/// they are bounded by the bytes they hold as well as by their count, evicting
+/// the least recently used first.
+struct IndexCache {
+  entries: SizedCache<String, Arc<PackageIndex>>,
+  max_entries: usize,
+  size: u64,
+  max_size: u64,
 }
 
-#[inline]
-fn encode_package_name(pkg_name: impl AsRef<str>) -> String {
-  let pkg_name: &str = pkg_name.as_ref();
-  if is_scoped_package_name(pkg_name) {
-    format!("@{}", encode(&pkg_name[1..]))
-  } else {
-    encode(pkg_name).to_string()
+impl IndexCache {
+  fn new(max_entries: usize, max_size: u64) -> Self {
+    Self {
+      entries: SizedCache::with_size(max_entries),
+      max_entries,
+      size: 0,
+      max_size,
+    }
   }
-}
 
-#[derive(Debug, Clone, Deserialize, Default)]
-#[serde(rename_all = "kebab-case")]
-struct PackageInfo {
-  versions: Option<HashMap<String, PackageConfig>>,
-  dist_tags: Option<HashMap<String, String>>,
-}
+  fn get(&mut self, key: &str) -> Option<Arc<PackageIndex>> {
+    self.entries.cache_get(&key.to_owned()).cloned()
+  }
+
+  fn remove(&mut self, key: &str) {
+    if let Some(index) = self.entries.cache_remove(&key.to_owned()) {
+      self.size -= index.unpacked_size() as u64;
+    }
+  }
 
-async fn fetch_pkg_info(package_name: impl AsRef<str>) -> anyhow::Result<PackageInfo> {
-  let package_name = package_name.as_ref();
-  let name = encode_package_name(package_name);
-  let info_url = format!("{}/{name}", *NPM_REGISTRY_URL);
-
-  tracing::debug!(
-    "Fetching package info for {} from {}",
-    package_name,
-    info_url
-  );
-
-  let res = REQUEST_CLIENT.get(info_url).send().await?;
-  let code = res.status();
-  if code == StatusCode::OK {
-    let res = res.json::<PackageInfo>().await?;
-    Ok(res)
-  } else {
-    let content = res.text().await?;
-    tracing::error!(
-      "Error fetching info for {} (status: {})",
-      package_name,
-      code
-    );
-    anyhow::bail!(content)
+  /// An index larger than `max_size` on its own is not kept.
+  fn insert(&mut self, key: String, index: Arc<PackageIndex>) {
+    let size = index.unpacked_size() as u64;
+    if size > self.max_size {
+      return;
+    }
+    self.remove(&key);
+    while self.entries.cache_size() >= self.max_entries || self.size + size > self.max_size {
+      let Some(lru) = self.entries.key_order().last().cloned() else {
+        break;
+      };
+      self.remove(&lru);
+    }
+    self.size += size;
+    self.entries.cache_set(key, index);
   }
 }
 
-#[derive(Debug, Clone, Deserialize)]
-pub struct VersionsAndTags {
-  pub versions: Vec<String>,
-  pub tags: HashMap<String, String>,
+/// How long a stale version list waits after a refresh started before
+/// another one may start, so a registry outage is not retried on every request.
+const REFRESH_BACKOFF: Duration = Duration::from_secs(10);
+
+#[derive(Clone)]
+struct CachedVersions {
+  value: VersionsAndTags,
+  fetched_at: Instant,
+  refreshed_at: Option<Instant>,
+}
+
+/// Version lists older than `ttl` are served stale while a background task
+/// refreshes them, for up to `grace` more, so they outlive registry outages.
+/// Both count from `fetched_at`: once `ttl + grace` has passed the list is
+/// only served if the registry answers again.
+#[derive(Clone)]
+struct VersionsCache {
+  upstream: Arc<Upstream>,
+  entries: Arc<Mutex<SizedCache<String, CachedVersions>>>,
+  flight: Arc<SingleFlight<Result<VersionsAndTags, AppError>>>,
+  ttl: Duration,
+  grace: Duration,
 }
 
-#[cached(
-  size = 200,
-  time = 300,
-  sync_writes = true,
-  result = true,
-  key = "String",
-  convert = r#"{ format!("versions-{}",package_name.as_ref()) }"#
-)]
-pub async fn get_versions_and_tags(
-  package_name: impl AsRef<str>,
-) -> anyhow::Result<VersionsAndTags> {
-  let info = fetch_pkg_info(package_name).await?;
-  match info.versions {
-    Some(versions) => Ok(VersionsAndTags {
-      versions: versions
-        .keys()
-        .map(|k| k.to_owned())
-        .collect::<Vec<String>>(),
-      tags: info.dist_tags.unwrap_or_default(),
-    }),
-    None => anyhow::bail!("Get package versions fail"),
+impl VersionsCache {
+  async fn fetch(&self, package_name: &str) -> Result<VersionsAndTags, AppError> {
+    self
+      .flight
+      .run(package_name, || async {
+        let value = VersionsAndTags::from(self.upstream.packument(package_name).await?);
+        self.entries.lock().unwrap().cache_set(
+          package_name.to_owned(),
+          CachedVersions {
+            value: value.clone(),
+            fetched_at: Instant::now(),
+            refreshed_at: None,
+          },
+        );
+        Ok(value)
+      })
+      .await
+  }
+
+  async fn get(&self, package_name: &str) -> Result<(VersionsAndTags, bool), AppError> {
+    let stale = {
+      let mut entries = self.entries.lock().unwrap();
+      match entries.cache_get_mut(&package_name.to_owned()) {
+        Some(cached) if cached.fetched_at.elapsed() < self.ttl => {
+          return Ok((cached.value.clone(), false));
+        }
+        Some(cached) if cached.fetched_at.elapsed() < self.ttl + self.grace => {
+          // One refresh per backoff, whether the last one failed or still runs.
+          let refresh = !matches!(cached.refreshed_at, Some(at) if at.elapsed() < REFRESH_BACKOFF);
+          if refresh {
+            cached.refreshed_at = Some(Instant::now());
+          }
+          Some((cached.value.clone(), refresh))
+        }
+        _ => None,
+      }
+    };
+
+    match stale {
+      Some((value, refresh)) => {
+        if refresh {
+          let this = self.clone();
+          let package_name = package_name.to_owned();
+          tokio::spawn(async move {
+            if let Err(e) = this.fetch(&package_name).await {
+              tracing::warn!("Error refreshing versions of {package_name}: {e}");
+            }
+          });
+        }
+        Ok((value, true))
+      }
+      None => Ok((self.fetch(package_name).await?, false)),
+    }
   }
 }
 
-#[derive(Debug, Clone)]
-pub struct SearchEntry {
-  pub found_entry: Option<Entry>,
-  pub matching_entries: HashMap<String, Entry>,
+/// Fetches package data from a [`Registry`], caching version lists, package
+/// configs and package indexes in memory and tarballs on disk.
+///
+/// Concurrent misses for the same package share one upstream fetch.
+pub struct NpmClient {
+  upstream: Arc<Upstream>,
+  tarball_cache: TarballCache,
+  versions: VersionsCache,
+  configs: Mutex<TimedSizedCache<String, PackageConfig>>,
+  indexes: Mutex<IndexCache>,
+  max_unpacked_size: u64,
+  packument_flight: SingleFlight<Result<Arc<Packument>, AppError>>,
+  tarball_flight: SingleFlight<Result<Bytes, AppError>>,
+  index_flight: SingleFlight<Result<Arc<PackageIndex>, AppError>>,
 }
 
-#[cached(
-  size = 200,
-  time = 300,
-  sync_writes = true,
-  option = true,
-  key = "String",
-  convert = r#"{ format!("config-{}-{}",package_name.as_ref(),version.as_ref()) }"#
-)]
-pub async fn get_package_config(
-  package_name: impl AsRef<str>,
-  version: impl AsRef<str>,
-) -> Option<PackageConfig> {
-  let version = version.as_ref();
-  match fetch_pkg_info(package_name).await {
-    Ok(info) => info
-      .versions
-      .and_then(|versions| versions.get(version).map(ToOwned::to_owned)),
-    Err(_) => None,
+impl NpmClient {
+  pub fn new(registry: Arc<dyn Registry>, config: &Config) -> anyhow::Result<Self> {
+    let upstream = Arc::new(Upstream {
+      registry,
+      published: config.publish.dir.as_ref().map(FsRegistry::new),
+      packuments: PackumentCache::open(&config.cache)?,
+      offline: config.offline,
+    });
+
+    Ok(Self {
+      versions: VersionsCache {
+        upstream: upstream.clone(),
+        entries: Arc::new(Mutex::new(SizedCache::with_size(200))),
+        flight: Arc::new(SingleFlight::new()),
+        ttl: Duration::from_secs(config.cache.versions_ttl),
+        grace: Duration::from_secs(config.cache.versions_grace),
+      },
+      upstream,
+      tarball_cache: TarballCache::open(&config.cache)?,
+      configs: Mutex::new(TimedSizedCache::with_size_and_lifespan(200, 300)),
+      indexes: Mutex::new(IndexCache::new(
+        config.cache.max_indexes.max(1),
+        config.cache.max_index_size,
+      )),
+      max_unpacked_size: config.limits.max_unpacked_size,
+      packument_flight: SingleFlight::new(),
+      tarball_flight: SingleFlight::new(),
+      index_flight: SingleFlight::new(),
+    })
   }
-}
 
-pub async fn get_package(
-  package_name: impl AsRef<str>,
-  version: impl AsRef<str>,
-) -> anyhow::Result<Bytes> {
-  let package_name = package_name.as_ref();
-  let version = version.as_ref();
-  let tarball_name = if is_scoped_package_name(package_name) {
-    package_name.split('/').nth(1).unwrap_or_default()
-  } else {
-    package_name
-  };
+  /// The versions and dist-tags of a package, and whether they are stale.
+  pub async fn get_versions_and_tags(
+    &self,
+    package_name: impl AsRef<str>,
+  ) -> Result<(VersionsAndTags, bool), AppError> {
+    self.versions.get(package_name.as_ref()).await
+  }
+
+  /// The full packument of a package, as served to npm clients.
+  pub async fn get_packument(
+    &self,
+    package_name: impl AsRef<str>,
+  ) -> Result<Arc<Packument>, AppError> {
+    let package_name = package_name.as_ref();
+    self
+      .packument_flight
+      .run(package_name, || async {
+        Ok(Arc::new(self.upstream.packument(package_name).await?))
+      })
+      .await
+  }
+
+  pub async fn get_package_config(
+    &self,
+    package_name: impl AsRef<str>,
+    version: impl AsRef<str>,
+  ) -> Option<PackageConfig> {
+    let package_name = package_name.as_ref();
+    let version = version.as_ref();
+    let key = format!("{package_name}@{version}");
+    if let Some(config) = self.configs.lock().unwrap().cache_get(&key) {
+      return Some(config.clone());
+    }
+
+    let packument = self.get_packument(package_name).await.ok()?;
+    let config = packument.versions.get(version)?.clone();
+    self.configs.lock().unwrap().cache_set(key, config.clone());
+    Some(config)
+  }
+
+  pub async fn get_package(
+    &self,
+    package_name: impl AsRef<str>,
+    version: impl AsRef<str>,
+    integrity: Option<String>,
+  ) -> Result<Bytes, AppError> {
+    let package_name = package_name.as_ref();
+    let version = version.as_ref();
+    let key = format!("{package_name}@{version}");
+
+    if let Some(ref integrity) = integrity {
+      if let Some(content) = self.tarball_cache.get(&key, integrity).await {
+        return Ok(content);
+      }
+    }
+
+    // Callers expecting different integrities must not share a result.
+    let flight_key = format!("{key} {}", integrity.as_deref().unwrap_or_default());
+    self
+      .tarball_flight
+      .run(&flight_key, || async {
+        let resp = self.upstream.tarball(package_name, version).await?;
 
-  let tarball_url = format!(
-    "{}/{package_name}/-/{tarball_name}-{version}.tgz",
-    *NPM_REGISTRY_URL
-  );
+        if let Some(integrity) = integrity {
+          if !verify_integrity(&resp, &integrity) {
+            tracing::error!("Tarball for {key} does not match {integrity}");
+            return Err(AppError::TarballIntegrityMismatch {
+              package_spec: key,
+              integrity,
+            });
+          }
+          if let Err(e) = self.tarball_cache.put(&key, &integrity, &resp).await {
+            tracing::warn!("Error caching tarball for {key}: {e}");
+          }
+        }
+
+        Ok(resp)
+      })
+      .await
+  }
+
+  /// Downloads the tarball of `package_name@version` into the disk cache,
+  /// failing when it cannot be kept there, so it is available offline.
+  pub async fn cache_package(
+    &self,
+    package_name: &str,
+    version: &str,
+    integrity: String,
+  ) -> Result<(), AppError> {
+    let key = format!("{package_name}@{version}");
+    let content = self
+      .get_package(package_name, version, Some(integrity.clone()))
+      .await?;
+    if !self.tarball_cache.contains(&key, &integrity).await {
+      self.tarball_cache.put(&key, &integrity, &content).await?;
+    }
+    Ok(())
+  }
+
+  /// Stores a version published to this server and forgets the cached
+  /// version list of its package, so the version resolves right away.
+  pub async fn publish(
+    &self,
+    package_name: &str,
+    version: &str,
+    config: PackageConfig,
+    dist_tags: HashMap<String, String>,
+    tarball: &[u8],
+  ) -> Result<(), AppError> {
+    let Some(published) = self.upstream.published.as_ref() else {
+      return Err(anyhow::anyhow!("publishing is not enabled").into());
+    };
+    published
+      .publish(package_name, version, config, dist_tags, tarball)
+      .await?;
+    self
+      .versions
+      .entries
+      .lock()
+      .unwrap()
+      .cache_remove(&package_name.to_owned());
+    Ok(())
+  }
 
-  tracing::debug!("Fetching package for {package_name} from {tarball_url}");
+  /// The file index of `package_name@version`, built from its tarball once and
+  /// shared by every request until it falls out of the cache.
+  pub async fn get_package_index(
+    &self,
+    package_name: impl AsRef<str>,
+    version: impl AsRef<str>,
+    integrity: Option<String>,
+  ) -> Result<Arc<PackageIndex>, AppError> {
+    let package_name = package_name.as_ref();
+    let version = version.as_ref();
+    let key = format!("{package_name}@{version}");
+    if let Some(index) = self.indexes.lock().unwrap().get(&key) {
+      return Ok(index);
+    }
 
-  let resp = reqwest::get(tarball_url).await?;
-  let resp = resp.bytes().await?;
+    self
+      .index_flight
+      .run(&key, || async {
+        let tarball = self.get_package(package_name, version, integrity).await?;
+        let index = PackageIndex::from_tarball(&key, &tarball, self.max_unpacked_size).await?;
+        let index = Arc::new(index);
+        tracing::debug!("Indexed {key} ({} bytes unpacked)", index.unpacked_size());
 
-  Ok(resp)
+        self
+          .indexes
+          .lock()
+          .unwrap()
+          .insert(key.clone(), index.clone());
+        Ok(index)
+      })
+      .await
+  }
+}
+
+#[poem::async_trait]
+impl<'a> FromRequest<'a> for &'a NpmClient {
+  async fn from_request(req: &'a Request, _: &mut RequestBody) -> poem::Result<Self> {
+    req
+      .extensions()
+      .get::<Arc<NpmClient>>()
+      .map(|npm| npm.as_ref())
+      .ok_or(anyhow::anyhow!(
+        "get npm client from the request extensions"
+      ))
+      .map_err(Into::into)
+  }
 }
 
 #[cfg(test)]
 mod tests {
+  use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
   use super::*;
+  use crate::{
+    config::CacheConfig,
+    registry::{create_tarball, MemoryRegistry, Packument},
+  };
+
+  /// Counts upstream fetches and makes tarball fetches slow enough to
+  /// overlap. Packuments fail while `down` is set.
+  #[derive(Default)]
+  struct CountingRegistry {
+    inner: MemoryRegistry,
+    packuments: AtomicUsize,
+    tarballs: AtomicUsize,
+    down: AtomicBool,
+  }
+
+  #[poem::async_trait]
+  impl Registry for CountingRegistry {
+    async fn packument(&self, package_name: &str) -> anyhow::Result<Packument> {
+      self.packuments.fetch_add(1, Ordering::SeqCst);
+      if self.down.load(Ordering::SeqCst) {
+        anyhow::bail!("registry is down");
+      }
+      self.inner.packument(package_name).await
+    }
+
+    async fn tarball(&self, package_name: &str, version: &str) -> anyhow::Result<Bytes> {
+      self.tarballs.fetch_add(1, Ordering::SeqCst);
+      tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+      self.inner.tarball(package_name, version).await
+    }
+  }
 
   #[tokio::test]
-  async fn test_fetch_pkg_info() -> anyhow::Result<()> {
-    fetch_pkg_info("builtins").await?;
+  async fn test_get_package_checks_integrity() -> anyhow::Result<()> {
+    let registry = MemoryRegistry::new();
+    registry.insert(
+      serde_json::json!({ "name": "pkg", "version": "1.0.0" }),
+      Bytes::from_static(b"tarball"),
+    )?;
+    let npm = NpmClient::new(Arc::new(registry), &Config::default())?;
 
+    let config = npm.get_package_config("pkg", "1.0.0").await.unwrap();
+    let content = npm.get_package("pkg", "1.0.0", config.integrity()).await?;
+    assert_eq!(content, "tarball");
+
+    let result = npm
+      .get_package("pkg", "1.0.0", Some("sha512-tampered".into()))
+      .await;
+    assert!(matches!(
+      result,
+      Err(AppError::TarballIntegrityMismatch { .. })
+    ));
     Ok(())
   }
 
-  // #[tokio::test]
-  // async fn test_get_versions_and_tags() -> anyhow::Result<()> {
-  //   let _vt = get_versions_and_tags("builtins").await?;
-  //   let vt = get_versions_and_tags("antd").await?;
-  //   println!("{:?}", vt);
-  //   Ok(())
-  // }
+  #[tokio::test]
+  async fn test_concurrent_fetches_are_coalesced() -> anyhow::Result<()> {
+    let registry = Arc::new(CountingRegistry::default());
+    registry.inner.insert(
+      serde_json::json!({ "name": "pkg", "version": "1.0.0" }),
+      create_tarball(&[("/index.js", "export default 1")]).await?,
+    )?;
+    let npm = Arc::new(NpmClient::new(registry.clone(), &Config::default())?);
+    let integrity = npm
+      .get_package_config("pkg", "1.0.0")
+      .await
+      .unwrap()
+      .integrity();
+
+    let tasks = (0..10)
+      .map(|_| {
+        let npm = npm.clone();
+        let integrity = integrity.clone();
+        tokio::spawn(async move { npm.get_package_index("pkg", "1.0.0", integrity).await })
+      })
+      .collect::<Vec<_>>();
+    for task in tasks {
+      assert!(task.await?.is_ok());
+    }
+    assert_eq!(registry.tarballs.load(Ordering::SeqCst), 1);
+
+    let tasks = (0..10)
+      .map(|_| {
+        let npm = npm.clone();
+        tokio::spawn(async move { npm.get_package("pkg", "2.0.0", None).await })
+      })
+      .collect::<Vec<_>>();
+    for task in tasks {
+      assert!(task.await?.is_err());
+    }
+    assert_eq!(registry.tarballs.load(Ordering::SeqCst), 2);
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_stale_versions_are_served_and_refreshed() -> anyhow::Result<()> {
+    let registry = Arc::new(CountingRegistry::default());
+    let package = |version| serde_json::json!({ "name": "pkg", "version": version });
+    registry.inner.insert(package("1.0.0"), Bytes::new())?;
+    let config = Config {
+      cache: CacheConfig {
+        versions_ttl: 0,
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    let npm = NpmClient::new(registry.clone(), &config)?;
+
+    let (vt, stale) = npm.get_versions_and_tags("pkg").await?;
+    assert_eq!((vt.tags["latest"].as_str(), stale), ("1.0.0", false));
+
+    registry.inner.insert(package("1.1.0"), Bytes::new())?;
+    let (vt, stale) = npm.get_versions_and_tags("pkg").await?;
+    assert_eq!((vt.tags["latest"].as_str(), stale), ("1.0.0", true));
+
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    registry.down.store(true, Ordering::SeqCst);
+    let (vt, stale) = npm.get_versions_and_tags("pkg").await?;
+    assert_eq!((vt.tags["latest"].as_str(), stale), ("1.1.0", true));
+
+    // The refresh that just failed is not retried before the backoff.
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    let fetched = registry.packuments.load(Ordering::SeqCst);
+    for _ in 0..5 {
+      let (vt, stale) = npm.get_versions_and_tags("pkg").await?;
+      assert_eq!((vt.tags["latest"].as_str(), stale), ("1.1.0", true));
+    }
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert_eq!(registry.packuments.load(Ordering::SeqCst), fetched);
+
+    let npm = NpmClient::new(registry.clone(), &config)?;
+    assert!(npm.get_versions_and_tags("pkg").await.is_err());
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_offline_serves_only_cached_packages() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut config = Config {
+      cache: CacheConfig {
+        dir: Some(dir.path().to_path_buf()),
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    let registry = MemoryRegistry::new();
+    registry.insert(
+      serde_json::json!({ "name": "pkg", "version": "1.0.0" }),
+      create_tarball(&[("/index.js", "export default 1")]).await?,
+    )?;
+    let npm = NpmClient::new(Arc::new(registry), &config)?;
+    let integrity = npm
+      .get_package_config("pkg", "1.0.0")
+      .await
+      .unwrap()
+      .integrity();
+    npm.get_package_index("pkg", "1.0.0", integrity).await?;
+
+    config.offline = true;
+    let npm = NpmClient::new(Arc::new(MemoryRegistry::new()), &config)?;
+    let (vt, _) = npm.get_versions_and_tags("pkg").await?;
+    assert_eq!(vt.tags["latest"], "1.0.0");
+    let integrity = npm
+      .get_package_config("pkg", "1.0.0")
+      .await
+      .unwrap()
+      .integrity();
+    let index = npm.get_package_index("pkg", "1.0.0", integrity).await?;
+    assert!(index.file("/index.js").is_some());
+
+    assert!(matches!(
+      npm.get_versions_and_tags("other").await,
+      Err(AppError::NotAvailableOffline(_))
+    ));
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_index_cache_is_bounded_by_size() -> anyhow::Result<()> {
+    let tarball = create_tarball(&[("/index.js", "export default 1")]).await?;
+    let index = Arc::new(PackageIndex::from_tarball("pkg", &tarball, u64::MAX).await?);
+    let size = index.unpacked_size() as u64;
+
+    let mut cache = IndexCache::new(10, 2 * size);
+    cache.insert("a".into(), index.clone());
+    cache.insert("b".into(), index.clone());
+    assert!(cache.get("a").is_some());
+    cache.insert("c".into(), index.clone());
+    assert!(cache.get("b").is_none());
+    assert!(cache.get("a").is_some() && cache.get("c").is_some());
+    assert_eq!(cache.size, 2 * size);
+
+    let mut cache = IndexCache::new(10, size - 1);
+    cache.insert("a".into(), index);
+    assert!(cache.get("a").is_none());
+    assert_eq!(cache.size, 0);
+    Ok(())
+  }
 }
@@ -0,0 +1,96 @@
+use std::ops::Range;
+
+/// More ranges than this in one request are ignored and the whole file is
+/// sent instead.
+const MAX_RANGES: usize = 16;
+
+/// Parses a `Range: bytes=..` header for content of `len` bytes. `None` means
+/// the header cannot be used and is ignored, while an empty list means none of
+/// the ranges can be satisfied.
+pub fn parse_range(value: &str, len: u64) -> Option<Vec<Range<u64>>> {
+  let (unit, specs) = value.split_once('=')?;
+  if !unit.trim().eq_ignore_ascii_case("bytes") {
+    return None;
+  }
+
+  let specs = specs.split(',').map(str::trim).collect::<Vec<_>>();
+  if specs.len() > MAX_RANGES {
+    return None;
+  }
+  let mut ranges = Vec::with_capacity(specs.len());
+  for spec in specs {
+    let (start, end) = spec.split_once('-')?;
+    let range = match (start, end) {
+      ("", suffix) => {
+        let suffix = suffix.parse::<u64>().ok()?;
+        len.saturating_sub(suffix)..len
+      }
+      (start, "") => start.parse::<u64>().ok()?..len,
+      (start, end) => {
+        let (start, end) = (start.parse::<u64>().ok()?, end.parse::<u64>().ok()?);
+        if end < start {
+          return None;
+        }
+        start..end.saturating_add(1).min(len)
+      }
+    };
+    if range.start < range.end {
+      ranges.push(range);
+    }
+  }
+  Some(ranges)
+}
+
+/// A `multipart/byteranges` body with one part per range.
+pub fn multipart_byteranges(
+  content: &[u8],
+  ranges: &[Range<u64>],
+  content_type: &str,
+  boundary: &str,
+) -> Vec<u8> {
+  let len = content.len();
+  let mut body = Vec::new();
+  for range in ranges {
+    body.extend_from_slice(
+      format!(
+        "--{boundary}\r\nContent-Type: {content_type}\r\nContent-Range: {}\r\n\r\n",
+        content_range(range, len as u64)
+      )
+      .as_bytes(),
+    );
+    body.extend_from_slice(&content[range.start as usize..range.end as usize]);
+    body.extend_from_slice(b"\r\n");
+  }
+  body.extend_from_slice(format!("--{boundary}--\r\n").as_bytes());
+  body
+}
+
+#[inline]
+pub fn content_range(range: &Range<u64>, len: u64) -> String {
+  format!("bytes {}-{}/{len}", range.start, range.end - 1)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(value: &str) -> Option<Vec<(u64, u64)>> {
+    parse_range(value, 1000).map(|ranges| ranges.iter().map(|r| (r.start, r.end)).collect())
+  }
+
+  #[test]
+  fn test_parse_range() {
+    assert_eq!(parse("bytes=0-499"), Some(vec![(0, 500)]));
+    assert_eq!(parse("bytes=500-"), Some(vec![(500, 1000)]));
+    assert_eq!(parse("bytes=-200"), Some(vec![(800, 1000)]));
+    assert_eq!(parse("bytes=-2000"), Some(vec![(0, 1000)]));
+    assert_eq!(parse("bytes=900-1999"), Some(vec![(900, 1000)]));
+    assert_eq!(parse("bytes=0-0, -1"), Some(vec![(0, 1), (999, 1000)]));
+
+    assert_eq!(parse("bytes=1000-"), Some(vec![]));
+    assert_eq!(parse("bytes=-0"), Some(vec![]));
+    assert_eq!(parse("bytes=5-1"), None);
+    assert_eq!(parse("bytes=a-b"), None);
+    assert_eq!(parse("items=0-1"), None);
+  }
+}
@@ -0,0 +1,92 @@
+use std::{
+  collections::HashMap,
+  future::Future,
+  sync::{Arc, Mutex},
+};
+
+use tokio::sync::OnceCell;
+
+/// Deduplicates concurrent calls by key: while a call is in flight, later
+/// callers with the same key wait for it and get a clone of its result.
+///
+/// Results are not kept once the call finishes, caching is up to the caller.
+pub struct SingleFlight<T> {
+  calls: Mutex<HashMap<String, Arc<OnceCell<T>>>>,
+}
+
+impl<T: Clone> SingleFlight<T> {
+  pub fn new() -> Self {
+    Self {
+      calls: Mutex::new(HashMap::new()),
+    }
+  }
+
+  pub async fn run<F, Fut>(&self, key: &str, f: F) -> T
+  where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = T>,
+  {
+    let call = self
+      .calls
+      .lock()
+      .unwrap()
+      .entry(key.to_owned())
+      .or_default()
+      .clone();
+
+    // If the caller running `f` is dropped, a waiting caller takes over.
+    let result = call.get_or_init(f).await.clone();
+
+    let mut calls = self.calls.lock().unwrap();
+    if calls.get(key).is_some_and(|c| Arc::ptr_eq(c, &call)) {
+      calls.remove(key);
+    }
+    result
+  }
+}
+
+impl<T: Clone> Default for SingleFlight<T> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+  };
+
+  use super::*;
+
+  #[tokio::test]
+  async fn test_concurrent_calls_share_one_result() {
+    let flight = Arc::new(SingleFlight::<Result<usize, String>>::new());
+    let calls = Arc::new(AtomicUsize::new(0));
+
+    let tasks = (0..10).map(|_| {
+      let flight = flight.clone();
+      let calls = calls.clone();
+      tokio::spawn(async move {
+        flight
+          .run("pkg", || async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Err::<usize, _>(format!(
+              "failed {} times",
+              calls.fetch_add(1, Ordering::SeqCst) + 1
+            ))
+          })
+          .await
+      })
+    });
+    for task in tasks.collect::<Vec<_>>() {
+      assert_eq!(task.await.unwrap(), Err("failed 1 times".into()));
+    }
+
+    // Finished calls are forgotten, so the next one runs again.
+    let result = flight.run("pkg", || async { Ok(1) }).await;
+    assert_eq!(result, Ok(1));
+    assert!(flight.calls.lock().unwrap().is_empty());
+  }
+}
@@ -1,54 +1,151 @@
 use std::sync::Arc;
 
-use once_cell::sync::Lazy;
-use swc::{self, try_with_handler};
+use mime_guess::Mime;
+use swc::{self, config::Options, try_with_handler, TransformOutput};
 use swc_common::{comments::SingleThreadedComments, errors::ColorConfig, SourceMap, GLOBALS};
-use swc_core::ecma::{transforms::base::pass::noop, visit::as_folder};
+use swc_core::ecma::{
+  ast::{Program, Str},
+  transforms::base::pass::noop,
+  visit::{as_folder, Fold},
+};
 
-use crate::models::PackageConfig;
+use crate::{
+  config::{JsxConfig, JsxRuntime},
+  models::PackageConfig,
+};
 
-static ORIGIN: Lazy<&'static str> =
-  Lazy::new(|| option_env!("ORIGIN").unwrap_or("https://unpkg.com"));
+/// Runs the swc compiler over `code` with `options` and two custom passes.
+fn process<P1: Fold, P2: Fold>(
+  code: String,
+  options: &Options,
+  before_pass: impl FnOnce(&Program) -> P1,
+  after_pass: impl FnOnce(&Program) -> P2,
+) -> anyhow::Result<TransformOutput> {
+  let cm = Arc::<SourceMap>::default();
+
+  let compiler = swc::Compiler::new(cm.clone());
+
+  GLOBALS.set(&Default::default(), || {
+    try_with_handler(
+      cm.clone(),
+      swc::HandlerOpts {
+        color: ColorConfig::Auto,
+        skip_filename: false,
+      },
+      |handler| {
+        let fm = cm.new_source_file(swc_common::FileName::Anon, code);
+
+        compiler.process_js_with_custom_pass(
+          fm,
+          None,
+          handler,
+          options,
+          SingleThreadedComments::default(),
+          before_pass,
+          after_pass,
+        )
+      },
+    )
+  })
+}
 
 pub fn rewrite_javascript_esmodule(
   code: String,
+  origin: &str,
   package_config: &PackageConfig,
 ) -> anyhow::Result<String> {
-  let cm = Arc::<SourceMap>::default();
+  process(
+    code,
+    &Default::default(),
+    |_| {
+      as_folder(path_url_rewrite::TransformVisitor::new(
+        origin,
+        package_config.dependencies(),
+      ))
+    },
+    |_| noop(),
+  )
+  .map(|r| r.code)
+}
 
-  let compiler = swc::Compiler::new(cm.clone());
+/// Sources that are compiled to JavaScript before they are served as modules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+  Jsx,
+  TypeScript,
+  Tsx,
+}
+
+impl Dialect {
+  /// The dialect of a file typed by `fs::get_content_type`.
+  pub fn from_content_type(content_type: &Mime) -> Option<Self> {
+    match content_type.essence_str() {
+      "text/jsx" => Some(Self::Jsx),
+      "application/typescript" => Some(Self::TypeScript),
+      "text/tsx" => Some(Self::Tsx),
+      _ => None,
+    }
+  }
+}
+
+/// Strips types and compiles JSX, then rewrites imports like
+/// `rewrite_javascript_esmodule`. The rewrite runs last, so the
+/// `jsx-runtime` import added by the automatic runtime is rewritten too.
+pub fn transpile_module(
+  code: String,
+  dialect: Dialect,
+  jsx: &JsxConfig,
+  origin: &str,
+  package_config: &PackageConfig,
+) -> anyhow::Result<String> {
+  let parser = match dialect {
+    Dialect::Jsx => serde_json::json!({ "syntax": "ecmascript", "jsx": true }),
+    Dialect::TypeScript => serde_json::json!({ "syntax": "typescript" }),
+    Dialect::Tsx => serde_json::json!({ "syntax": "typescript", "tsx": true }),
+  };
+  let react = match jsx.runtime {
+    JsxRuntime::Automatic => serde_json::json!({
+      "runtime": "automatic",
+      "importSource": jsx.import_source,
+    }),
+    JsxRuntime::Classic => serde_json::json!({
+      "runtime": "classic",
+      "pragma": jsx.pragma,
+      "pragmaFrag": jsx.pragma_frag,
+    }),
+  };
+  let options: Options = serde_json::from_value(serde_json::json!({
+    "swcrc": false,
+    "jsc": {
+      "parser": parser,
+      "target": "es2022",
+      "transform": { "react": react },
+    },
+  }))?;
 
-  GLOBALS
-    .set(&Default::default(), || {
-      try_with_handler(
-        cm.clone(),
-        swc::HandlerOpts {
-          color: ColorConfig::Auto,
-          skip_filename: false,
-        },
-        |handler| {
-          //   let fm = cm
-          //     .load_file(Path::new("turntable/examples/transform-input.js"))
-          //     .expect("failed to load file");
-
-          let fm = cm.new_source_file(swc_common::FileName::Anon, code);
-
-          compiler.process_js_with_custom_pass(
-            fm,
-            None,
-            handler,
-            &Default::default(),
-            SingleThreadedComments::default(),
-            |_| {
-              as_folder(path_url_rewrite::TransformVisitor::new(
-                *ORIGIN,
-                package_config.dependencies(),
-              ))
-            },
-            |_| noop(),
-          )
-        },
-      )
-    })
-    .map(|r| r.code)
+  process(
+    code,
+    &options,
+    |_| noop(),
+    |_| {
+      as_folder(path_url_rewrite::TransformVisitor::new(
+        origin,
+        package_config.dependencies(),
+      ))
+    },
+  )
+  .map(|r| r.code)
+}
+
+/// Rewrites one specifier the way `rewrite_javascript_esmodule` rewrites
+/// imports, for urls outside of JavaScript like `<script src>`.
+pub fn rewrite_module_specifier(
+  specifier: &str,
+  origin: &str,
+  package_config: &PackageConfig,
+) -> String {
+  let mut visitor = path_url_rewrite::TransformVisitor::new(origin, package_config.dependencies());
+  let mut value = Str::from(specifier);
+  visitor.rewrite_value(&mut value);
+  value.value.to_string()
 }
@@ -1,3 +1,34 @@
+use poem::{
+  http::{header, uri::Authority},
+  Request,
+};
+
+/// Where a nested route is mounted, like `/browse`. `Route::nest` strips it
+/// from the uri, so it is kept as request data for redirects to stay under it.
+#[derive(Debug, Clone, Copy)]
+pub struct MountPrefix(pub &'static str);
+
+#[inline]
+pub fn mount_prefix(req: &Request) -> &'static str {
+  req
+    .extensions()
+    .get::<MountPrefix>()
+    .map_or("", |prefix| prefix.0)
+}
+
+/// The origin a request was sent to, from its `Host` and scheme. The scheme
+/// of a proxy terminating TLS comes from `X-Forwarded-Proto`.
+pub fn request_origin(req: &Request) -> Option<String> {
+  let header = |name| req.headers().get(name).and_then(|v| v.to_str().ok());
+  let host = header(header::HOST.as_str())
+    .or_else(|| req.uri().authority().map(Authority::as_str))
+    .filter(|host| host.parse::<Authority>().is_ok())?;
+  let scheme = header("x-forwarded-proto")
+    .filter(|scheme| matches!(*scheme, "http" | "https"))
+    .unwrap_or(req.scheme().as_str());
+  Some(format!("{scheme}://{host}"))
+}
+
 pub fn create_pkg_url(
   package_name: impl AsRef<str>,
   package_version: impl AsRef<str>,
@@ -17,5 +48,8 @@ pub fn create_pkg_url(
     url += filename;
   }
 
-  url + query.unwrap_or_default()
+  match query {
+    Some(query) if !query.is_empty() => format!("{url}?{query}"),
+    _ => url,
+  }
 }